@@ -1,3 +1,13 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
 use aoc_runner_derive::{aoc, aoc_generator};
 
 type Input = (Vec<u32>, Vec<u32>);
@@ -50,8 +60,254 @@ pub fn part2((l1, l2): &Input) -> u32 {
     total
 }
 
+/// Statistics of the two lists, see [`analyze`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub distance: u64,
+    pub similarity: u64,
+    /// lower median of the distances between the paired values
+    pub median_distance: Option<u32>,
+    /// number of values of the left list for each number of occurrences in the right list
+    pub similarity_histogram: BTreeMap<usize, usize>,
+}
+
+/// Most runs merged at once, to bound the number of open files.
+const MAX_FAN_IN: usize = 64;
+
+static SPILL_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Sorted run written to disk, removed when dropped.
+#[derive(Debug)]
+struct Spill {
+    path: PathBuf,
+}
+
+impl Spill {
+    fn write(dir: &Path, values: impl IntoIterator<Item = io::Result<u32>>) -> io::Result<Self> {
+        let id = SPILL_ID.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("aoc2024-day1-{}-{id}.run", process::id()));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let spill = Spill { path };
+        let mut w = BufWriter::new(file);
+        for v in values {
+            w.write_all(&v?.to_le_bytes())?;
+        }
+        w.flush()?;
+        Ok(spill)
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// External merge sort keeping at most `run_len` values in memory, the rest being spilled
+/// to sorted runs in `dir`.
+#[derive(Debug)]
+pub struct ExternalSorter {
+    run_len: usize,
+    dir: PathBuf,
+    run: Vec<u32>,
+    spills: Vec<Spill>,
+    len: usize,
+}
+
+impl ExternalSorter {
+    pub fn new(run_len: usize, dir: impl Into<PathBuf>) -> Self {
+        let run_len = run_len.max(1);
+        ExternalSorter {
+            run_len,
+            dir: dir.into(),
+            run: Vec::with_capacity(run_len),
+            spills: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, v: u32) -> io::Result<()> {
+        self.run.push(v);
+        self.len += 1;
+        if self.run.len() >= self.run_len {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        self.run.sort_unstable();
+        let spill = Spill::write(&self.dir, self.run.drain(..).map(Ok))?;
+        self.spills.push(spill);
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<Sorted> {
+        if !self.run.is_empty() {
+            self.spill()?;
+        }
+        // merge the oldest runs together until they can all be opened at once
+        while self.spills.len() > MAX_FAN_IN {
+            let group = Sorted {
+                spills: self.spills.drain(..MAX_FAN_IN).collect(),
+                len: 0,
+            };
+            let merged = Spill::write(&self.dir, group.iter()?)?;
+            self.spills.push(merged);
+        }
+        Ok(Sorted {
+            spills: std::mem::take(&mut self.spills),
+            len: self.len,
+        })
+    }
+}
+
+/// Sorted values spilled on disk, which can be read as many times as needed.
+#[derive(Debug)]
+pub struct Sorted {
+    spills: Vec<Spill>,
+    len: usize,
+}
+
+impl Sorted {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Merges the runs, only keeping the head of each run in memory.
+    pub fn iter(&self) -> io::Result<SortedIter> {
+        let mut it = SortedIter {
+            readers: Vec::with_capacity(self.spills.len()),
+            heap: BinaryHeap::new(),
+        };
+        for spill in &self.spills {
+            it.readers.push(BufReader::new(File::open(&spill.path)?));
+            it.advance(it.readers.len() - 1)?;
+        }
+        Ok(it)
+    }
+}
+
+/// Iterator over the values of [`Sorted`], in increasing order.
+pub struct SortedIter {
+    readers: Vec<BufReader<File>>,
+    // next value of each run that isn't exhausted, with the index of the run
+    heap: BinaryHeap<Reverse<(u32, usize)>>,
+}
+
+impl SortedIter {
+    fn advance(&mut self, run: usize) -> io::Result<()> {
+        let mut buf = [0; 4];
+        match self.readers[run].read_exact(&mut buf) {
+            Ok(()) => self.heap.push(Reverse((u32::from_le_bytes(buf), run))),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for SortedIter {
+    type Item = io::Result<u32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((v, run)) = self.heap.pop()?;
+        Some(self.advance(run).map(|_| v))
+    }
+}
+
+/// Computes both answers and more statistics on lists too large to be sorted in memory,
+/// keeping at most `run_len` values of each list in memory and spilling the rest in `dir`.
+pub fn analyze<R: BufRead>(reader: R, run_len: usize, dir: &Path) -> io::Result<Stats> {
+    let mut left = ExternalSorter::new(run_len, dir);
+    let mut right = ExternalSorter::new(run_len, dir);
+    for l in reader.lines() {
+        let l = l?;
+        if l.trim().is_empty() {
+            continue;
+        }
+        let mut it = l.split_whitespace().map(|v| v.parse::<u32>());
+        match (it.next(), it.next(), it.next()) {
+            (Some(Ok(v1)), Some(Ok(v2)), None) => {
+                left.push(v1)?;
+                right.push(v2)?;
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid line: {l:?}"),
+                ))
+            }
+        }
+    }
+    let left = left.finish()?;
+    let right = right.finish()?;
+
+    let mut distance = 0;
+    let mut distances = ExternalSorter::new(run_len, dir);
+    for (v1, v2) in left.iter()?.zip(right.iter()?) {
+        let d = v1?.abs_diff(v2?);
+        distance += d as u64;
+        distances.push(d)?;
+    }
+    let distances = distances.finish()?;
+    let median_distance = if distances.is_empty() {
+        None
+    } else {
+        distances
+            .iter()?
+            .nth((distances.len() - 1) / 2)
+            .transpose()?
+    };
+
+    // same merge as part 2, on the streams
+    let mut similarity = 0;
+    let mut similarity_histogram = BTreeMap::new();
+    let mut right = right.iter()?.peekable();
+    let mut left = left.iter()?.peekable();
+    while let Some(v) = left.next().transpose()? {
+        let mut left_count = 1;
+        while let Some(Ok(next)) = left.peek() {
+            if *next != v {
+                break;
+            }
+            left.next();
+            left_count += 1;
+        }
+        let mut right_count = 0;
+        while let Some(next) = right.peek() {
+            match next {
+                Ok(next) if *next > v => break,
+                Ok(next) => {
+                    right_count += (*next == v) as usize;
+                    right.next();
+                }
+                Err(_) => return Err(right.next().unwrap().unwrap_err()),
+            }
+        }
+        similarity += v as u64 * right_count as u64 * left_count as u64;
+        *similarity_histogram.entry(right_count).or_insert(0) += left_count;
+    }
+
+    Ok(Stats {
+        distance,
+        similarity,
+        median_distance,
+        similarity_histogram,
+    })
+}
+
 #[cfg(test)]
 mod test {
+    use crate::test_utils::Rng;
+
     use super::*;
 
     const EXAMPLE: &str = r#"3   4
@@ -70,4 +326,38 @@ mod test {
     fn part2_example() {
         assert_eq!(part2(&parse(EXAMPLE)), 31);
     }
+
+    #[test]
+    fn analyze_example() {
+        let stats = analyze(EXAMPLE.as_bytes(), 2, &std::env::temp_dir()).unwrap();
+        assert_eq!(stats.distance, 11);
+        assert_eq!(stats.similarity, 31);
+        // distances are 2, 1, 0, 1, 2, 5
+        assert_eq!(stats.median_distance, Some(1));
+        // 1 and 2 never appear on the right, 4 once and 3 three times
+        assert_eq!(
+            stats.similarity_histogram,
+            BTreeMap::from([(0, 2), (1, 1), (3, 3)])
+        );
+    }
+
+    #[test]
+    fn analyze_spilled() {
+        let mut rng = Rng::new(11);
+        let mut rand = || rng.below(1000);
+        let input = (0..2000)
+            .map(|_| format!("{}   {}", rand(), rand()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let parsed = parse(&input);
+        for run_len in [1, 7, 100, 10000] {
+            let stats = analyze(input.as_bytes(), run_len, &std::env::temp_dir()).unwrap();
+            assert_eq!(stats.distance, part1(&parsed) as u64);
+            assert_eq!(stats.similarity, part2(&parsed) as u64);
+            assert_eq!(stats.similarity_histogram.values().sum::<usize>(), 2000);
+        }
+        assert!(analyze("1 2\n3".as_bytes(), 2, &std::env::temp_dir()).is_err());
+        let empty = analyze("".as_bytes(), 2, &std::env::temp_dir()).unwrap();
+        assert_eq!(empty.median_distance, None);
+    }
 }
@@ -1,11 +1,8 @@
-use std::collections::VecDeque;
-
 use aoc_runner_derive::{aoc, aoc_generator};
 use aoc_utils::{
     grid::Grid,
     point::{Point, ORTHO},
 };
-use gxhash::{HashSet, HashSetExt};
 
 type Input = Grid<u8>;
 
@@ -14,59 +11,178 @@ pub fn parse(input: &str) -> Input {
     Grid::parse(input)
 }
 
-fn pathfind(grid: &Grid<u8>, start: Point, p1: bool) -> usize {
-    let mut queue = VecDeque::new();
-    let mut seen = HashSet::new();
-    queue.push_back((start, 0));
-    seen.insert(start);
-
-    let mut score = 0;
-    while let Some((pos, height)) = queue.pop_front() {
-        if height == 9 {
-            score += 1;
-            continue;
+/// Rules of a hiking trail: from `start` to `end`, changing height by `step` at each move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrailSpec {
+    pub start: u8,
+    pub end: u8,
+    pub step: i8,
+}
+
+impl Default for TrailSpec {
+    fn default() -> Self {
+        TrailSpec {
+            start: 0,
+            end: 9,
+            step: 1,
         }
-        for dir in ORTHO {
-            if grid.contains(pos + dir) {
-                let nh = grid[pos + dir] - b'0';
-                if nh == height + 1 {
-                    if p1 && !seen.insert(pos + dir) {
+    }
+}
+
+/// Scores of a trailhead, see [`TrailSpec::scores`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrailheadScore {
+    pub trailhead: Point,
+    /// number of distinct trail ends reachable
+    pub score: usize,
+    /// number of distinct trails
+    pub rating: usize,
+}
+
+fn height(grid: &Grid<u8>, i: usize) -> Option<u8> {
+    let b = grid.bytes[i];
+    b.is_ascii_digit().then(|| b - b'0')
+}
+
+fn index(grid: &Grid<u8>, p: Point) -> usize {
+    p.y as usize * grid.width + p.x as usize
+}
+
+impl TrailSpec {
+    /// Heights of the trail in order, empty if `end` can't be reached from `start` or if a
+    /// height isn't a digit.
+    pub fn levels(&self) -> Vec<u8> {
+        // the heights go from start to end, they are all digits when both are
+        if self.start > 9 || self.end > 9 {
+            return Vec::new();
+        }
+        let diff = self.end as i32 - self.start as i32;
+        let step = self.step as i32;
+        if step == 0 {
+            return if diff == 0 {
+                vec![self.start]
+            } else {
+                Vec::new()
+            };
+        }
+        if diff % step != 0 || diff / step < 0 {
+            return Vec::new();
+        }
+        (0..=diff / step)
+            .map(|k| (self.start as i32 + k * step) as u8)
+            .collect()
+    }
+
+    /// Every trail starting at `trailhead`, as the list of its points.
+    pub fn trails(&self, grid: &Grid<u8>, trailhead: Point) -> Vec<Vec<Point>> {
+        let levels = self.levels();
+        let mut res = Vec::new();
+        if levels.is_empty()
+            || !grid.contains(trailhead)
+            || height(grid, index(grid, trailhead)) != Some(levels[0])
+        {
+            return res;
+        }
+        // depth first, with the index of the next direction to try at each point
+        let mut path = vec![trailhead];
+        let mut next_dir = vec![0];
+        while let Some(&pos) = path.last() {
+            if path.len() == levels.len() {
+                res.push(path.clone());
+                path.pop();
+                next_dir.pop();
+                continue;
+            }
+            let di = next_dir.last_mut().unwrap();
+            if *di == ORTHO.len() {
+                path.pop();
+                next_dir.pop();
+                continue;
+            }
+            let np = pos + ORTHO[*di];
+            *di += 1;
+            if grid.contains(np) && height(grid, index(grid, np)) == Some(levels[path.len()]) {
+                path.push(np);
+                next_dir.push(0);
+            }
+        }
+        res
+    }
+
+    /// Scores of every trailhead in reading order, computed from the trail ends down to the
+    /// trailheads one height at a time, so each point is only visited once. The reachable ends
+    /// are kept as bitsets.
+    pub fn scores(&self, grid: &Grid<u8>) -> Vec<TrailheadScore> {
+        let levels = self.levels();
+        let Some(&last) = levels.last() else {
+            return Vec::new();
+        };
+        let mut by_height: [Vec<usize>; 10] = Default::default();
+        for i in 0..grid.bytes.len() {
+            if let Some(h) = height(grid, i) {
+                by_height[h as usize].push(i);
+            }
+        }
+
+        let ends = &by_height[last as usize];
+        let words = ends.len().div_ceil(64);
+        let mut reach = vec![Vec::new(); grid.bytes.len()];
+        let mut rating = vec![0; grid.bytes.len()];
+        for (ei, &i) in ends.iter().enumerate() {
+            reach[i] = vec![0u64; words];
+            reach[i][ei / 64] |= 1 << (ei % 64);
+            rating[i] = 1;
+        }
+        for w in levels.windows(2).rev() {
+            let (h, above) = (w[0], w[1]);
+            for &i in &by_height[h as usize] {
+                let p = grid.as_point(i);
+                let mut bits = vec![0u64; words];
+                for dir in ORTHO {
+                    if !grid.contains(p + dir) {
                         continue;
                     }
-                    queue.push_front((pos + dir, nh));
+                    let ni = index(grid, p + dir);
+                    if height(grid, ni) == Some(above) {
+                        rating[i] += rating[ni];
+                        bits.iter_mut().zip(&reach[ni]).for_each(|(b, nb)| *b |= nb);
+                    }
                 }
+                reach[i] = bits;
+            }
+            // the upper level isn't needed anymore
+            for &i in &by_height[above as usize] {
+                reach[i] = Vec::new();
             }
         }
+
+        by_height[levels[0] as usize]
+            .iter()
+            .map(|&i| TrailheadScore {
+                trailhead: grid.as_point(i),
+                score: reach[i].iter().map(|b| b.count_ones() as usize).sum(),
+                rating: rating[i],
+            })
+            .collect()
     }
-    score
 }
 
 #[aoc(day10, part1)]
 pub fn part1(input: &Input) -> u32 {
-    let grid = input;
-    let mut score = 0;
-    for (bi, b) in grid.bytes.iter().enumerate() {
-        if *b == b'0' {
-            score += pathfind(grid, grid.as_point(bi), true);
-        }
-    }
-    score as u32
+    let scores = TrailSpec::default().scores(input);
+    scores.iter().map(|s| s.score).sum::<usize>() as u32
 }
 
 #[aoc(day10, part2)]
 pub fn part2(input: &Input) -> u32 {
-    let grid = input;
-    let mut score = 0;
-    for (bi, b) in grid.bytes.iter().enumerate() {
-        if *b == b'0' {
-            score += pathfind(grid, grid.as_point(bi), false);
-        }
-    }
-    score as u32
+    let scores = TrailSpec::default().scores(input);
+    scores.iter().map(|s| s.rating).sum::<usize>() as u32
 }
 
 #[cfg(test)]
 mod test {
+    use gxhash::HashSet;
+
     use super::*;
 
     const EXAMPLE: &str = r#"89010123
@@ -87,4 +203,78 @@ mod test {
     fn part2_example() {
         assert_eq!(part2(&parse(EXAMPLE)), 81);
     }
+
+    #[test]
+    fn trails_match_scores() {
+        let grid = parse(EXAMPLE);
+        let specs = [
+            TrailSpec::default(),
+            TrailSpec {
+                start: 9,
+                end: 0,
+                step: -1,
+            },
+            TrailSpec {
+                start: 1,
+                end: 7,
+                step: 2,
+            },
+            TrailSpec {
+                start: 4,
+                end: 4,
+                step: 0,
+            },
+        ];
+        for spec in specs {
+            for s in spec.scores(&grid) {
+                let trails = spec.trails(&grid, s.trailhead);
+                assert_eq!(s.rating, trails.len(), "{spec:?}");
+                let ends = trails.iter().map(|t| *t.last().unwrap());
+                assert_eq!(s.score, ends.collect::<HashSet<_>>().len(), "{spec:?}");
+            }
+        }
+        // the scores are symmetric when walking the trails downhill
+        let down = TrailSpec {
+            start: 9,
+            end: 0,
+            step: -1,
+        }
+        .scores(&grid);
+        assert_eq!(down.iter().map(|s| s.rating).sum::<usize>(), 81);
+    }
+
+    #[test]
+    fn trail_points() {
+        let grid = parse(
+            r#"...0...
+...1...
+...2...
+6543456
+7.....7
+8.....8
+9.....9"#,
+        );
+        let spec = TrailSpec::default();
+        let trails = spec.trails(&grid, Point::new(3, 0));
+        assert_eq!(trails.len(), 2);
+        assert_eq!(trails[0][9], Point::new(6, 6));
+        assert_eq!(trails[1][9], Point::new(0, 6));
+        assert!(spec.trails(&grid, Point::new(0, 0)).is_empty());
+        let unreachable = TrailSpec {
+            start: 0,
+            end: 9,
+            step: 2,
+        };
+        assert!(unreachable.levels().is_empty());
+        assert!(unreachable.scores(&grid).is_empty());
+        let too_high = TrailSpec {
+            start: 0,
+            end: 12,
+            step: 1,
+        };
+        assert!(too_high.scores(&grid).is_empty());
+        assert!(too_high.trails(&grid, Point::new(3, 0)).is_empty());
+        assert!(spec.trails(&grid, Point::new(-1, 0)).is_empty());
+        assert!(spec.trails(&grid, Point::new(3, 7)).is_empty());
+    }
 }
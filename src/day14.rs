@@ -1,6 +1,8 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use aoc_utils::{grid::Grid, point::Point};
 
+use crate::math::gcd;
+
 type Input = Vec<(Point, Point)>;
 
 #[aoc_generator(day14)]
@@ -79,6 +81,223 @@ pub fn part1(input: &Input) -> usize {
     count_quadrants(&grid)
 }
 
+/// How to recognise the frame where the robots draw the Christmas tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeDetector {
+    /// the tree base is a horizontal line of robots
+    Line,
+    /// the robots are the most grouped together, by the variance of their positions
+    Variance,
+    /// the robots are the most grouped together, by the entropy of their rows and columns
+    Entropy,
+    /// find the most grouped step on each axis separately, then combine both periods with the
+    /// chinese remainder theorem
+    Crt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeFrame {
+    pub step: usize,
+    pub grid: String,
+}
+
+/// Renders the room like in the puzzle statement, with the number of robots on each tile.
+pub fn render(positions: &[Point], width: i64, height: i64) -> String {
+    let mut counts = vec![0u32; (width * height) as usize];
+    for p in positions {
+        counts[(p.y * width + p.x) as usize] += 1;
+    }
+    counts
+        .chunks(width as usize)
+        .map(|row| {
+            row.iter()
+                .map(|&c| match c {
+                    0 => '.',
+                    c => char::from_digit(c.min(9), 10).unwrap(),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Variance of the values, scaled by the number of values squared to stay in integers.
+fn variance(vals: impl Iterator<Item = i64>) -> i64 {
+    let (mut n, mut sum, mut sum_sq) = (0, 0, 0);
+    for v in vals {
+        n += 1;
+        sum += v;
+        sum_sq += v * v;
+    }
+    n * sum_sq - sum * sum
+}
+
+/// Shannon entropy of the distribution of the values in `0..size`.
+fn entropy(vals: impl Iterator<Item = i64>, size: i64) -> f64 {
+    let mut counts = vec![0usize; size as usize];
+    let mut n = 0;
+    for v in vals {
+        counts[v as usize] += 1;
+        n += 1;
+    }
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / n as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn has_line(positions: &[Point], width: i64, height: i64) -> bool {
+    let mut occupied = vec![false; (width * height) as usize];
+    for p in positions {
+        occupied[(p.y * width + p.x) as usize] = true;
+    }
+    // based on an assumption: the robots for the tree base should form a line
+    let line_length = 10;
+    occupied
+        .chunks(width as usize)
+        .any(|row| row.windows(line_length).any(|w| w.iter().all(|&o| o)))
+}
+
+/// Step in `0..period` minimising `score`.
+fn best_step<F: Fn(i64) -> f64>(period: i64, score: F) -> i64 {
+    (0..period)
+        .min_by(|&a, &b| score(a).total_cmp(&score(b)))
+        .unwrap()
+}
+
+fn lcm(a: i64, b: i64) -> i64 {
+    a / gcd(a, b) * b
+}
+
+/// Solves `a * t = b (mod m)`, returning the solutions as `t = r (mod m')`.
+fn solve_congruence(a: i64, b: i64, m: i64) -> Option<(i64, i64)> {
+    let (a, b) = (a.rem_euclid(m), b.rem_euclid(m));
+    let g = gcd(a, m);
+    if b % g != 0 {
+        return None;
+    }
+    let m = m / g;
+    let (a, b) = (a / g, b / g);
+    // a and m are now coprime, m is small enough to search for the inverse directly
+    let inv = (0..m).find(|&x| (a * x) % m == 1 % m)?;
+    Some(((b * inv) % m, m))
+}
+
+/// Combines `t = r1 (mod m1)` and `t = r2 (mod m2)` with the chinese remainder theorem.
+fn crt((r1, m1): (i64, i64), (r2, m2): (i64, i64)) -> Option<(i64, i64)> {
+    let (k, _) = solve_congruence(m1, r2 - r1, m2)?;
+    let l = lcm(m1, m2);
+    Some(((r1 + m1 * k).rem_euclid(l), l))
+}
+
+/// Robots moving in a room of configurable dimensions, wrapping around the edges.
+#[derive(Debug, Clone)]
+pub struct RobotSwarm {
+    pub robots: Vec<(Point, Point)>,
+    pub width: i64,
+    pub height: i64,
+}
+
+impl RobotSwarm {
+    pub fn new(robots: &[(Point, Point)], width: i64, height: i64) -> Self {
+        RobotSwarm {
+            robots: robots.to_vec(),
+            width,
+            height,
+        }
+    }
+
+    /// Position of robot `i` at time `t`, which can be negative to go back in time.
+    pub fn position_at(&self, i: usize, t: i64) -> Point {
+        let (pos, vel) = self.robots[i];
+        // reduced on each axis first so that any t works without overflowing
+        let axis = |p: i64, v: i64, size: i64| {
+            (p.rem_euclid(size) + v.rem_euclid(size) * t.rem_euclid(size)).rem_euclid(size)
+        };
+        Point::new(
+            axis(pos.x, vel.x, self.width),
+            axis(pos.y, vel.y, self.height),
+        )
+    }
+
+    pub fn positions_at(&self, t: i64) -> Vec<Point> {
+        (0..self.robots.len())
+            .map(|i| self.position_at(i, t))
+            .collect()
+    }
+
+    /// Number of steps before robot `i` is back to its starting position.
+    pub fn period(&self, i: usize) -> i64 {
+        let (_, vel) = self.robots[i];
+        let px = self.width / gcd(vel.x.rem_euclid(self.width), self.width);
+        let py = self.height / gcd(vel.y.rem_euclid(self.height), self.height);
+        lcm(px, py)
+    }
+
+    /// First time when all the robots are back to their starting position together.
+    pub fn return_time(&self) -> i64 {
+        (0..self.robots.len()).fold(1, |acc, i| lcm(acc, self.period(i)))
+    }
+
+    /// First time `t >= 0` when robots `i` and `j` are on the same tile.
+    pub fn first_collision(&self, i: usize, j: usize) -> Option<i64> {
+        let ((pi, vi), (pj, vj)) = (self.robots[i], self.robots[j]);
+        // (vi - vj) * t = pj - pi on both axes
+        let tx = solve_congruence(vi.x - vj.x, pj.x - pi.x, self.width)?;
+        let ty = solve_congruence(vi.y - vj.y, pj.y - pi.y, self.height)?;
+        crt(tx, ty).map(|(t, _)| t)
+    }
+
+    /// Finds the first step where the robots draw the Christmas tree, the positions repeat
+    /// after `width * height` steps so there is no point in looking further.
+    pub fn find_tree(&self, detector: TreeDetector) -> Option<TreeFrame> {
+        let (width, height) = (self.width, self.height);
+        let period = width * height;
+        let step = match detector {
+            TreeDetector::Line => {
+                (0..period).find(|&t| has_line(&self.positions_at(t), width, height))?
+            }
+            TreeDetector::Variance => best_step(period, |t| {
+                let positions = self.positions_at(t);
+                (variance(positions.iter().map(|p| p.x)) + variance(positions.iter().map(|p| p.y)))
+                    as f64
+            }),
+            TreeDetector::Entropy => best_step(period, |t| {
+                let positions = self.positions_at(t);
+                entropy(positions.iter().map(|p| p.x), width)
+                    + entropy(positions.iter().map(|p| p.y), height)
+            }),
+            TreeDetector::Crt => {
+                // the x coordinates repeat every `width` steps and the y ones every `height` steps
+                let robots = &self.robots;
+                let tx = best_step(width, |t| {
+                    variance(
+                        robots
+                            .iter()
+                            .map(|(p, v)| (p.x + v.x * t).rem_euclid(width)),
+                    ) as f64
+                });
+                let ty = best_step(height, |t| {
+                    variance(
+                        robots
+                            .iter()
+                            .map(|(p, v)| (p.y + v.y * t).rem_euclid(height)),
+                    ) as f64
+                });
+                crt((tx, width), (ty, height))?.0
+            }
+        };
+        Some(TreeFrame {
+            step: step as usize,
+            grid: render(&self.positions_at(step), width, height),
+        })
+    }
+}
+
 #[aoc(day14, part2)]
 pub fn part2(input: &Input) -> usize {
     let robots = input;
@@ -86,41 +305,16 @@ pub fn part2(input: &Input) -> usize {
         // test case
         return 0;
     }
-    let mut grid = fill_robots(robots);
-    let mut robots = robots.clone();
-    let mut i = 0;
-    loop {
-        for (pos, vel) in robots.iter_mut() {
-            let mut next_pos = *pos + *vel;
-            next_pos.x = next_pos.x.rem_euclid(grid.width as i64);
-            next_pos.y = next_pos.y.rem_euclid(grid.height as i64);
-            grid[*pos] -= 1;
-            grid[next_pos] += 1;
-            *pos = next_pos;
-        }
-        i += 1;
-
-        // based on an assumption: the robots for the tree base should form a line
-        const DIR: Point = Point::new(1, 0);
-        let line_length = 10;
-        for &(mut p, _) in &robots {
-            let mut is_tree = true;
-            for _ in 0..line_length {
-                if !grid.contains(p) || grid[p] == 0 {
-                    is_tree = false;
-                    break;
-                }
-                p += DIR;
-            }
-            if is_tree {
-                return i;
-            }
-        }
-    }
+    RobotSwarm::new(robots, 101, 103)
+        .find_tree(TreeDetector::Crt)
+        .unwrap()
+        .step
 }
 
 #[cfg(test)]
 mod test {
+    use crate::test_utils::Rng;
+
     use super::*;
 
     const EXAMPLE: &str = r#"p=0,4 v=3,-3
@@ -145,4 +339,94 @@ p=9,5 v=-3,-3 "#;
     fn part2_example() {
         assert_eq!(part2(&parse(EXAMPLE)), 0);
     }
+
+    /// Robots drawing a filled triangle at step `tree_step`, with noise around it.
+    fn tree_swarm(tree_step: i64) -> Vec<(Point, Point)> {
+        let (width, height) = (101, 103);
+        let mut rng = Rng::new(42);
+        let mut rand = |n: i64| rng.below(n as u64) as i64;
+        let mut positions = Vec::new();
+        for y in 0..20 {
+            for x in 50 - y..=50 + y {
+                positions.push(Point::new(x, 40 + y));
+            }
+        }
+        for _ in 0..100 {
+            positions.push(Point::new(rand(width), rand(height)));
+        }
+        positions
+            .into_iter()
+            .map(|p| {
+                let vel = Point::new(rand(width) - width / 2, rand(height) - height / 2);
+                let start = p - vel * tree_step;
+                (
+                    Point::new(start.x.rem_euclid(width), start.y.rem_euclid(height)),
+                    vel,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn find_tree_detectors() {
+        let swarm = RobotSwarm::new(&tree_swarm(4242), 101, 103);
+        for detector in [
+            TreeDetector::Line,
+            TreeDetector::Variance,
+            TreeDetector::Entropy,
+            TreeDetector::Crt,
+        ] {
+            let frame = swarm.find_tree(detector).unwrap();
+            assert_eq!(frame.step, 4242, "{detector:?}");
+            let lines = frame.grid.lines().collect::<Vec<_>>();
+            assert_eq!(lines.len(), 103);
+            assert!(lines[59].contains(&"1".repeat(39)));
+        }
+    }
+
+    #[test]
+    fn render_example() {
+        let swarm = RobotSwarm::new(&parse(EXAMPLE), 11, 7);
+        assert_eq!(
+            render(&swarm.positions_at(100), 11, 7),
+            "......2..1.\n\
+             ...........\n\
+             1..........\n\
+             .11........\n\
+             .....1.....\n\
+             ...12......\n\
+             .1....1...."
+        );
+    }
+
+    #[test]
+    fn swarm_queries() {
+        let swarm = RobotSwarm::new(&parse(EXAMPLE), 11, 7);
+        // p=2,4 v=2,-3 from the puzzle statement
+        assert_eq!(swarm.position_at(10, 5), Point::new(1, 3));
+        assert_eq!(swarm.position_at(10, -5), Point::new(3, 5));
+        // 77 is a multiple of both the width and the height
+        let far = i64::MAX / 2 / 77 * 77;
+        assert_eq!(swarm.position_at(10, far + 5), Point::new(1, 3));
+        assert_eq!(
+            swarm.position_at(10, i64::MIN + 5),
+            swarm.position_at(10, i64::MIN % 77 + 5)
+        );
+        for t in [-20, -1, 0, 3, 100] {
+            let positions = swarm.positions_at(t);
+            assert_eq!(swarm.positions_at(t + swarm.return_time()), positions);
+            for (i, &p) in positions.iter().enumerate() {
+                assert_eq!(swarm.position_at(i, t + swarm.period(i)), p);
+            }
+        }
+        assert_eq!(swarm.return_time(), 77);
+
+        for i in 0..swarm.robots.len() {
+            for j in i + 1..swarm.robots.len() {
+                let brute = (0..swarm.return_time())
+                    .find(|&t| swarm.position_at(i, t) == swarm.position_at(j, t));
+                assert_eq!(swarm.first_collision(i, j), brute);
+            }
+        }
+    }
 }
@@ -1,7 +1,219 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use gxhash::{HashMap, HashMapExt};
 
-type Input = (Vec<String>, Vec<String>);
+type Input = (Trie, Vec<String>);
+
+/// Prefix tree of the towel designs, built once and shared by all the patterns.
+#[derive(Debug, Clone)]
+pub struct Trie {
+    designs: Vec<String>,
+    nodes: Vec<TrieNode>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    // 0 means no child, the root can't be a child
+    children: [usize; 26],
+    // index of the design ending at this node
+    design: Option<usize>,
+}
+
+impl Trie {
+    /// Builds the trie, the designs can only contain lowercase letters.
+    pub fn new(designs: &[String]) -> Result<Self, String> {
+        let mut nodes = vec![TrieNode::default()];
+        for (di, design) in designs.iter().enumerate() {
+            if !design.bytes().all(|b| b.is_ascii_lowercase()) {
+                return Err(format!("invalid towel design: {design:?}"));
+            }
+            let mut cur = 0;
+            for b in design.bytes() {
+                let ci = (b - b'a') as usize;
+                if nodes[cur].children[ci] == 0 {
+                    nodes.push(TrieNode::default());
+                    nodes[cur].children[ci] = nodes.len() - 1;
+                }
+                cur = nodes[cur].children[ci];
+            }
+            nodes[cur].design = Some(di);
+        }
+        Ok(Trie {
+            designs: designs.to_vec(),
+            nodes,
+        })
+    }
+
+    /// Designs that are a prefix of `s`, from the shortest to the longest.
+    fn prefixes<'a>(&'a self, s: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        let mut cur = 0;
+        s.iter()
+            .map_while(move |&b| {
+                if !b.is_ascii_lowercase() {
+                    return None;
+                }
+                cur = self.nodes[cur].children[(b - b'a') as usize];
+                (cur != 0).then_some(cur)
+            })
+            .filter_map(|n| self.nodes[n].design)
+    }
+
+    /// `ways[i]` is the number of arrangements of `pattern[i..]`.
+    fn ways(&self, pattern: &str) -> Vec<usize> {
+        let pattern = pattern.as_bytes();
+        let mut ways = vec![0; pattern.len() + 1];
+        ways[pattern.len()] = 1;
+        for i in (0..pattern.len()).rev() {
+            ways[i] = self
+                .prefixes(&pattern[i..])
+                .map(|d| ways[i + self.designs[d].len()])
+                .sum();
+        }
+        ways
+    }
+
+    pub fn is_possible(&self, pattern: &str) -> bool {
+        let pattern = pattern.as_bytes();
+        let mut possible = vec![false; pattern.len() + 1];
+        possible[pattern.len()] = true;
+        for i in (0..pattern.len()).rev() {
+            possible[i] = self
+                .prefixes(&pattern[i..])
+                .any(|d| possible[i + self.designs[d].len()]);
+        }
+        possible[0]
+    }
+
+    /// Number of ways to arrange the towel designs to form `pattern`.
+    pub fn count_arrangements(&self, pattern: &str) -> usize {
+        self.ways(pattern)[0]
+    }
+
+    /// One arrangement of the towel designs forming `pattern`, if there is any.
+    pub fn decompose(&self, pattern: &str) -> Option<Vec<&str>> {
+        let ways = self.ways(pattern);
+        if ways[0] == 0 {
+            return None;
+        }
+        let mut res = Vec::new();
+        let mut i = 0;
+        while i < pattern.len() {
+            let d = self
+                .prefixes(&pattern.as_bytes()[i..])
+                .find(|&d| ways[i + self.designs[d].len()] > 0)
+                .unwrap();
+            res.push(self.designs[d].as_str());
+            i += self.designs[d].len();
+        }
+        Some(res)
+    }
+
+    /// Lazily iterates over every arrangement of the towel designs forming `pattern`, in
+    /// lexicographic order.
+    pub fn arrangements<'a>(&'a self, pattern: &'a str) -> Arrangements<'a> {
+        let ways = self.ways(pattern);
+        Arrangements {
+            trie: self,
+            pattern: pattern.as_bytes(),
+            done: ways[0] == 0,
+            ways,
+            stack: Vec::new(),
+            started: false,
+        }
+    }
+
+    /// An arrangement using the fewest towels, the first one in lexicographic order on ties.
+    pub fn shortest_arrangement(&self, pattern: &str) -> Option<Vec<&str>> {
+        let bytes = pattern.as_bytes();
+        // best[i] is the fewest towels needed for pattern[i..] and the first design to use
+        let mut best: Vec<Option<(usize, usize)>> = vec![None; bytes.len() + 1];
+        best[bytes.len()] = Some((0, 0));
+        for i in (0..bytes.len()).rev() {
+            for d in self.prefixes(&bytes[i..]) {
+                if let Some((count, _)) = best[i + self.designs[d].len()] {
+                    if best[i].is_none_or(|(c, _)| count + 1 < c) {
+                        best[i] = Some((count + 1, d));
+                    }
+                }
+            }
+        }
+        best[0]?;
+        let mut res = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            let (_, d) = best[i].unwrap();
+            res.push(self.designs[d].as_str());
+            i += self.designs[d].len();
+        }
+        Some(res)
+    }
+}
+
+/// Iterator over the arrangements of a pattern, see [`Trie::arrangements`].
+pub struct Arrangements<'a> {
+    trie: &'a Trie,
+    pattern: &'a [u8],
+    ways: Vec<usize>,
+    // towels of the current arrangement, as (start index, design)
+    stack: Vec<(usize, usize)>,
+    started: bool,
+    done: bool,
+}
+
+impl Arrangements<'_> {
+    /// The next design at index `i` longer than `min_len` that can still lead to an arrangement.
+    fn next_design(&self, i: usize, min_len: usize) -> Option<usize> {
+        self.trie.prefixes(&self.pattern[i..]).find(|&d| {
+            let len = self.trie.designs[d].len();
+            len > min_len && self.ways[i + len] > 0
+        })
+    }
+
+    /// Completes the current arrangement with the smallest designs.
+    fn descend(&mut self) {
+        let mut i = self
+            .stack
+            .last()
+            .map_or(0, |&(i, d)| i + self.trie.designs[d].len());
+        while i < self.pattern.len() {
+            let d = self.next_design(i, 0).unwrap();
+            self.stack.push((i, d));
+            i += self.trie.designs[d].len();
+        }
+    }
+}
+
+impl<'a> Iterator for Arrangements<'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if !self.started {
+            self.started = true;
+            self.descend();
+        } else {
+            // backtrack to the last towel that can be replaced by a longer one
+            loop {
+                let Some((i, d)) = self.stack.pop() else {
+                    self.done = true;
+                    return None;
+                };
+                if let Some(next) = self.next_design(i, self.trie.designs[d].len()) {
+                    self.stack.push((i, next));
+                    self.descend();
+                    break;
+                }
+            }
+        }
+        let trie = self.trie;
+        Some(
+            self.stack
+                .iter()
+                .map(|&(_, d)| trie.designs[d].as_str())
+                .collect(),
+        )
+    }
+}
 
 #[aoc_generator(day19)]
 pub fn parse(input: &str) -> Input {
@@ -14,67 +226,19 @@ pub fn parse(input: &str) -> Input {
     for rval in r.lines() {
         rules.push(rval.to_string());
     }
-    (designs, rules)
-}
-
-fn dp(
-    i: usize,
-    designs: &Vec<String>,
-    val: &str,
-    cache: &mut HashMap<usize, usize>,
-    p1: bool,
-) -> usize {
-    if let Some(&res) = cache.get(&i) {
-        return res;
-    }
-    if i == val.len() {
-        cache.insert(i, 1);
-        return 1;
-    }
-    if i > val.len() {
-        cache.insert(i, 0);
-        return 0;
-    }
-    let mut res = 0;
-    for d in designs {
-        if val[i..].starts_with(d) {
-            res += dp(i + d.len(), designs, val, cache, p1);
-            // for p1 we don't need to check all designs
-            if res > 0 && p1 {
-                return res;
-            }
-        }
-    }
-    cache.insert(i, res);
-    res
+    (Trie::new(&designs).unwrap(), rules)
 }
 
 #[aoc(day19, part1)]
 pub fn part1(input: &Input) -> usize {
-    let (designs, rules) = input;
-    let mut designs = designs.clone();
-    designs.sort();
-    let mut sum = 0;
-    for rule in rules {
-        let mut cache = HashMap::new();
-        if dp(0, &designs, rule, &mut cache, true) > 0 {
-            sum += 1;
-        }
-    }
-    sum
+    let (trie, rules) = input;
+    rules.iter().filter(|rule| trie.is_possible(rule)).count()
 }
 
 #[aoc(day19, part2)]
 pub fn part2(input: &Input) -> usize {
-    let (designs, rules) = input;
-    let mut designs = designs.clone();
-    designs.sort();
-    let mut sum = 0;
-    for rule in rules {
-        let mut cache = HashMap::new();
-        sum += dp(0, &designs, rule, &mut cache, false);
-    }
-    sum
+    let (trie, rules) = input;
+    rules.iter().map(|rule| trie.count_arrangements(rule)).sum()
 }
 
 #[cfg(test)]
@@ -101,4 +265,35 @@ bbrgwb"#;
     fn part2_example() {
         assert_eq!(part2(&parse(EXAMPLE)), 16);
     }
+
+    #[test]
+    fn decompose_example() {
+        let (trie, _) = parse(EXAMPLE);
+        assert_eq!(trie.count_arrangements("gbbr"), 4);
+        assert_eq!(trie.decompose("brwrr"), Some(vec!["b", "r", "wr", "r"]));
+        assert_eq!(trie.decompose("ubwu"), None);
+        assert_eq!(trie.decompose(""), Some(vec![]));
+        assert!(Trie::new(&["r".into(), "R".into()]).is_err());
+    }
+
+    #[test]
+    fn arrangements_example() {
+        let (trie, _) = parse(EXAMPLE);
+        assert_eq!(
+            trie.arrangements("gbbr").collect::<Vec<_>>(),
+            [
+                vec!["g", "b", "b", "r"],
+                vec!["g", "b", "br"],
+                vec!["gb", "b", "r"],
+                vec!["gb", "br"],
+            ]
+        );
+        assert_eq!(trie.arrangements("ubwu").next(), None);
+        assert_eq!(trie.arrangements("rrbgbr").count(), 6);
+        assert_eq!(
+            trie.shortest_arrangement("rrbgbr"),
+            Some(vec!["r", "rb", "g", "br"])
+        );
+        assert_eq!(trie.shortest_arrangement("bbrgwb"), None);
+    }
 }
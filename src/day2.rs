@@ -15,71 +15,138 @@ pub fn parse(input: &str) -> Input {
     res
 }
 
-fn is_valid(v1: u32, v2: u32, is_increasing: bool) -> bool {
-    let diff = v1.abs_diff(v2);
-    (1..=3).contains(&diff) && (v1 < v2) == is_increasing
+/// Direction the levels of a safe report must follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Increasing,
+    Decreasing,
+    /// either one, as long as it is the same for the whole report
+    Any,
 }
 
-#[aoc(day2, part1)]
-pub fn part1(input: &Input) -> u32 {
-    let mut total = 0;
-    'rep: for report in input {
-        let increasing = report[0] < report[1];
-        for chunk in report.windows(2) {
-            let [v1, v2] = chunk else {
-                unreachable!();
-            };
-            if !is_valid(*v1, *v2, increasing) {
-                continue 'rep;
-            }
+/// Rules deciding whether a report is safe, the puzzle ones by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SafetyPolicy {
+    pub min_step: u32,
+    pub max_step: u32,
+    /// whether two adjacent levels can be equal, whatever the minimum step
+    pub allow_plateaus: bool,
+    pub direction: Direction,
+}
+
+impl Default for SafetyPolicy {
+    fn default() -> Self {
+        SafetyPolicy {
+            min_step: 1,
+            max_step: 3,
+            allow_plateaus: false,
+            direction: Direction::Any,
         }
-        total += 1;
     }
-    total
 }
 
-#[aoc(day2, part2)]
-pub fn part2(input: &Input) -> u32 {
-    let mut total = 0;
-    for report in input {
-        // not really optimized but good enough
-        'rep: for idx in 0..report.len() {
-            let mut i = 0;
-            let mut j = 1;
-            if i == idx {
-                i += 1;
-                j += 1;
-            }
-            if j == idx {
-                j += 1;
-            }
-            let increasing = report[i] < report[j];
+impl SafetyPolicy {
+    fn directions(&self) -> &'static [bool] {
+        match self.direction {
+            Direction::Increasing => &[true],
+            Direction::Decreasing => &[false],
+            Direction::Any => &[true, false],
+        }
+    }
 
-            while j < report.len() {
-                let v1 = report[i];
-                let v2 = report[j];
-                if !is_valid(v1, v2, increasing) {
-                    continue 'rep;
-                }
+    fn is_valid(&self, v1: u32, v2: u32, is_increasing: bool) -> bool {
+        if v1 == v2 {
+            return self.allow_plateaus;
+        }
+        let diff = v1.abs_diff(v2);
+        (self.min_step..=self.max_step).contains(&diff) && (v1 < v2) == is_increasing
+    }
 
-                i += 1;
-                j += 1;
-                if i == idx {
-                    i += 1;
+    pub fn is_safe(&self, report: &[u32]) -> bool {
+        self.directions().iter().any(|&increasing| {
+            report
+                .windows(2)
+                .all(|w| self.is_valid(w[0], w[1], increasing))
+        })
+    }
+
+    /// Fewest levels to remove, at most `k`, to make the report safe, as sorted indices.
+    /// Linear in the report length for a fixed `k`: each kept level is only compared to the
+    /// `k + 1` levels following it.
+    pub fn dampened(&self, report: &[u32], k: usize) -> Option<Vec<usize>> {
+        let n = report.len();
+        if n == 0 {
+            return Some(Vec::new());
+        }
+        let mut best: Option<Vec<usize>> = None;
+        for &increasing in self.directions() {
+            // reached[i * (k + 1) + r]: level i kept last after r removals, with the previous state
+            let mut reached: Vec<Option<Option<(usize, usize)>>> = vec![None; n * (k + 1)];
+            for first in 0..=k.min(n - 1) {
+                reached[first * (k + 1) + first] = Some(None);
+            }
+            let mut end = None;
+            for i in 0..n {
+                for r in 0..=k {
+                    if reached[i * (k + 1) + r].is_none() {
+                        continue;
+                    }
+                    // remove all the remaining levels
+                    let total = r + n - 1 - i;
+                    if total <= k && end.is_none_or(|(_, _, t)| total < t) {
+                        end = Some((i, r, total));
+                    }
+                    for j in i + 1..n.min(i + 2 + k - r) {
+                        let r2 = r + j - i - 1;
+                        if self.is_valid(report[i], report[j], increasing)
+                            && reached[j * (k + 1) + r2].is_none()
+                        {
+                            reached[j * (k + 1) + r2] = Some(Some((i, r)));
+                        }
+                    }
                 }
-                if j == idx {
-                    j += 1;
+            }
+            let Some((mut i, mut r, total)) = end else {
+                continue;
+            };
+            if best.as_ref().is_some_and(|b| b.len() <= total) {
+                continue;
+            }
+            let mut kept = vec![false; n];
+            loop {
+                kept[i] = true;
+                match reached[i * (k + 1) + r].unwrap() {
+                    Some((pi, pr)) => (i, r) = (pi, pr),
+                    None => break,
                 }
             }
-            total += 1;
-            break;
+            best = Some((0..n).filter(|&i| !kept[i]).collect());
         }
+        best
     }
-    total
+}
+
+#[aoc(day2, part1)]
+pub fn part1(input: &Input) -> u32 {
+    let policy = SafetyPolicy::default();
+    input.iter().filter(|report| policy.is_safe(report)).count() as u32
+}
+
+#[aoc(day2, part2)]
+pub fn part2(input: &Input) -> u32 {
+    let policy = SafetyPolicy::default();
+    input
+        .iter()
+        .filter(|report| policy.dampened(report, 1).is_some())
+        .count() as u32
 }
 
 #[cfg(test)]
 mod test {
+    use itertools::Itertools;
+
+    use crate::test_utils::Rng;
+
     use super::*;
 
     const EXAMPLE: &str = r#"7 6 4 2 1
@@ -98,4 +165,93 @@ mod test {
     fn part2_example() {
         assert_eq!(part2(&parse(EXAMPLE)), 4);
     }
+
+    /// Checks every way of removing up to `k` levels.
+    fn brute_force(policy: &SafetyPolicy, report: &[u32], k: usize) -> Option<usize> {
+        let safe = |levels: &[u32]| policy.is_safe(levels);
+        (0..=k.min(report.len())).find(|&removed| {
+            (0..report.len()).combinations(removed).any(|indices| {
+                let levels = (0..report.len())
+                    .filter(|i| !indices.contains(i))
+                    .map(|i| report[i])
+                    .collect::<Vec<_>>();
+                safe(&levels)
+            })
+        })
+    }
+
+    #[test]
+    fn dampened_example() {
+        let reports = parse(EXAMPLE);
+        let dampened = |report: &[u32], k| SafetyPolicy::default().dampened(report, k);
+        assert_eq!(dampened(&reports[0], 0), Some(vec![]));
+        assert_eq!(dampened(&reports[1], 1), None);
+        assert_eq!(dampened(&reports[3], 1), Some(vec![2]));
+        assert_eq!(dampened(&reports[4], 1), Some(vec![3]));
+        assert_eq!(dampened(&[1, 9, 2, 8, 3], 2), Some(vec![1, 3]));
+    }
+
+    #[test]
+    fn custom_policy() {
+        let reports = parse(EXAMPLE);
+        let increasing = SafetyPolicy {
+            direction: Direction::Increasing,
+            ..SafetyPolicy::default()
+        };
+        assert!(!increasing.is_safe(&reports[0]));
+        assert!(increasing.is_safe(&reports[5]));
+        let lenient = SafetyPolicy {
+            max_step: 5,
+            allow_plateaus: true,
+            ..SafetyPolicy::default()
+        };
+        let safe = reports.iter().filter(|r| lenient.is_safe(r)).count();
+        // only 1 3 2 4 5 is still unsafe
+        assert_eq!(safe, 5);
+        assert_eq!(lenient.dampened(&reports[3], 1), Some(vec![2]));
+    }
+
+    #[test]
+    fn dampened_matches_brute_force() {
+        let mut rng = Rng::new(7);
+        let mut rand = |n: u64| rng.below(n);
+        let policies = [
+            SafetyPolicy::default(),
+            SafetyPolicy {
+                min_step: 2,
+                max_step: 4,
+                allow_plateaus: true,
+                direction: Direction::Any,
+            },
+            SafetyPolicy {
+                direction: Direction::Decreasing,
+                ..SafetyPolicy::default()
+            },
+        ];
+        for _ in 0..2000 {
+            let len = rand(9) as usize;
+            let mut report = vec![rand(20) as u32 + 10];
+            for _ in 1..len {
+                let last = *report.last().unwrap() as i64;
+                report.push((last + rand(9) as i64 - 4).max(0) as u32);
+            }
+            for policy in &policies {
+                for k in 0..=3 {
+                    let removed = policy.dampened(&report, k);
+                    assert_eq!(
+                        removed.as_ref().map(|r| r.len()),
+                        brute_force(policy, &report, k),
+                        "{report:?} {k} {policy:?}"
+                    );
+                    if let Some(removed) = removed {
+                        let levels = (0..report.len())
+                            .filter(|i| !removed.contains(i))
+                            .map(|i| report[i])
+                            .collect::<Vec<_>>();
+                        assert!(policy.is_safe(&levels), "{report:?} {k} {policy:?}");
+                    }
+                }
+            }
+        }
+    }
 }
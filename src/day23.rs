@@ -1,3 +1,5 @@
+use std::{collections::BTreeMap, fmt::Write};
+
 use aoc_runner_derive::{aoc, aoc_generator};
 use gxhash::{HashMap, HashMapExt, HashSet, HashSetExt};
 use itertools::Itertools;
@@ -11,51 +13,122 @@ pub fn parse(input: &str) -> Input {
     let mut name_map: HashMap<&str, Vec<&str>> = HashMap::new();
     for l in input.lines() {
         let (name, neigh) = l.split_once('-').unwrap();
-        if !name_i.contains_key(name) {
-            let id = name_i.len();
-            name_i.insert(name, id);
-            i_name.insert(id, name.to_string());
+        for n in [name, neigh] {
+            if !name_i.contains_key(n) {
+                let id = name_i.len();
+                name_i.insert(n, id);
+                i_name.insert(id, n.to_string());
+            }
         }
         name_map.entry(name).or_default().push(neigh);
         name_map.entry(neigh).or_default().push(name);
     }
 
-    // build the adjacency list graph
+    // build the adjacency list graph, sorted and without the links listed twice
     let mut graph = vec![Vec::new(); name_i.len()];
     for (name, neighs) in &name_map {
-        for neigh in neighs {
-            graph[name_i[name]].push(name_i[neigh]);
-        }
+        let neighbours = &mut graph[name_i[name]];
+        neighbours.extend(neighs.iter().map(|neigh| name_i[neigh]));
+        neighbours.sort_unstable();
+        neighbours.dedup();
     }
     (graph, i_name)
 }
 
-#[aoc(day23, part1)]
-pub fn part1(input: &Input) -> usize {
-    let (graph, i_name) = input;
-    let mut sum = 0;
-    let mut seen = vec![false; graph.len()];
-    for i in 0..graph.len() {
-        seen[i] = true;
-        for n1i in 0..graph[i].len() {
-            let n1 = graph[i][n1i];
-            for n2i in n1i + 1..graph[i].len() {
-                let n2 = graph[i][n2i];
-                // if we visited one of the nodes, the clique has already been found
-                if seen[n1] || seen[n2] {
-                    continue;
-                }
-                if graph[n1].contains(&n2)
-                    && (i_name[&i].starts_with("t")
-                        || i_name[&n1].starts_with("t")
-                        || i_name[&n2].starts_with("t"))
-                {
-                    sum += 1;
-                }
-            }
+/// Predicate on node names used to select cliques: a clique is kept if at least one of its
+/// nodes matches.
+#[derive(Debug, Clone)]
+pub enum NameFilter {
+    Any,
+    Prefix(String),
+    /// glob-like pattern, `?` matches a single character and `*` any sequence
+    Pattern(String),
+    Set(HashSet<String>),
+}
+
+impl NameFilter {
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NameFilter::Any => true,
+            NameFilter::Prefix(prefix) => name.starts_with(prefix.as_str()),
+            NameFilter::Pattern(pattern) => glob_match(pattern.as_bytes(), name.as_bytes()),
+            NameFilter::Set(names) => names.contains(name),
         }
     }
-    sum
+}
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], name) || (!name.is_empty() && glob_match(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+fn extend_cliques(
+    graph: &[Vec<usize>],
+    k: usize,
+    cur: &mut Vec<usize>,
+    candidates: &[usize],
+    res: &mut Vec<Vec<usize>>,
+) {
+    if cur.len() == k {
+        res.push(cur.clone());
+        return;
+    }
+    for (ci, &c) in candidates.iter().enumerate() {
+        // only keep the candidates connected to the new node, in increasing order to find
+        // each clique exactly once
+        let next = candidates[ci + 1..]
+            .iter()
+            .copied()
+            .filter(|n| graph[c].contains(n))
+            .collect_vec();
+        if cur.len() + 1 + next.len() < k {
+            continue;
+        }
+        cur.push(c);
+        extend_cliques(graph, k, cur, &next, res);
+        cur.pop();
+    }
+}
+
+/// Enumerates all the cliques of size `k` containing at least one node matching `filter`.
+/// Each clique is returned as a sorted list of names, and the cliques are sorted as well.
+pub fn k_cliques(input: &Input, k: usize, filter: &NameFilter) -> Vec<Vec<String>> {
+    let (graph, i_name) = input;
+    if k == 0 {
+        return Vec::new();
+    }
+    let mut cliques = Vec::new();
+    let mut cur = Vec::with_capacity(k);
+    for n in 0..graph.len() {
+        let candidates = graph[n].iter().copied().filter(|&c| c > n).collect_vec();
+        cur.push(n);
+        extend_cliques(graph, k, &mut cur, &candidates, &mut cliques);
+        cur.pop();
+    }
+
+    let mut res = cliques
+        .into_iter()
+        .filter(|clique| clique.iter().any(|i| filter.matches(&i_name[i])))
+        .map(|clique| {
+            let mut names = clique.iter().map(|i| i_name[i].clone()).collect_vec();
+            names.sort();
+            names
+        })
+        .collect_vec();
+    res.sort();
+    res
+}
+
+#[aoc(day23, part1)]
+pub fn part1(input: &Input) -> usize {
+    k_cliques(input, 3, &NameFilter::Prefix("t".to_string())).len()
 }
 
 // Bron-Kerbosch algorithm, not the most efficient
@@ -120,6 +193,135 @@ pub fn part2(input: &Input) -> String {
     names.join(",")
 }
 
+/// Every edge of the graph once, as sorted name pairs.
+fn edges(input: &Input) -> Vec<(&str, &str)> {
+    let (graph, i_name) = input;
+    let mut edges = Vec::new();
+    for (n1, neighbours) in graph.iter().enumerate() {
+        for &n2 in neighbours.iter().filter(|&&n2| n2 > n1) {
+            let (a, b) = (i_name[&n1].as_str(), i_name[&n2].as_str());
+            edges.push(if a < b { (a, b) } else { (b, a) });
+        }
+    }
+    edges.sort_unstable();
+    edges
+}
+
+fn sorted_names(input: &Input) -> Vec<&str> {
+    input.1.values().map(|n| n.as_str()).sorted().collect_vec()
+}
+
+/// Quoted DOT identifier, any name being valid once quoted.
+fn dot_id(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn xml_escape(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Exports the LAN as an undirected graph in the Graphviz DOT format.
+pub fn to_dot(input: &Input) -> String {
+    let mut out = String::from("graph lan {\n");
+    for name in sorted_names(input) {
+        writeln!(out, "    {};", dot_id(name)).unwrap();
+    }
+    for (a, b) in edges(input) {
+        writeln!(out, "    {} -- {};", dot_id(a), dot_id(b)).unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Exports the LAN as an undirected graph in the GraphML format.
+pub fn to_graphml(input: &Input) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    out.push_str("  <graph id=\"lan\" edgedefault=\"undirected\">\n");
+    for name in sorted_names(input) {
+        writeln!(out, "    <node id=\"{}\"/>", xml_escape(name)).unwrap();
+    }
+    for (a, b) in edges(input) {
+        let (a, b) = (xml_escape(a), xml_escape(b));
+        writeln!(out, "    <edge source=\"{a}\" target=\"{b}\"/>").unwrap();
+    }
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
+
+/// Connected components of the LAN, each one as a sorted list of names.
+pub fn connected_components(input: &Input) -> Vec<Vec<String>> {
+    let (graph, i_name) = input;
+    let mut components = Vec::new();
+    let mut seen = vec![false; graph.len()];
+    for start in 0..graph.len() {
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut component = Vec::new();
+        let mut stack = vec![start];
+        while let Some(n) = stack.pop() {
+            component.push(i_name[&n].clone());
+            for &neigh in &graph[n] {
+                if !seen[neigh] {
+                    seen[neigh] = true;
+                    stack.push(neigh);
+                }
+            }
+        }
+        component.sort();
+        components.push(component);
+    }
+    components.sort();
+    components
+}
+
+/// Number of nodes for each degree.
+pub fn degree_distribution(input: &Input) -> BTreeMap<usize, usize> {
+    let mut dist = BTreeMap::new();
+    for neighbours in &input.0 {
+        *dist.entry(neighbours.len()).or_default() += 1;
+    }
+    dist
+}
+
+/// Core number of each node: the largest `k` such that the node belongs to the k-core, the
+/// maximal subgraph where every node has a degree of at least `k`.
+pub fn k_core_decomposition(input: &Input) -> HashMap<String, usize> {
+    let (graph, i_name) = input;
+    let mut degree = graph.iter().map(|n| n.len()).collect_vec();
+    let mut removed = vec![false; graph.len()];
+    let mut cores = HashMap::with_capacity(graph.len());
+    let mut k = 0;
+    // repeatedly peel off the node with the smallest remaining degree
+    while let Some(n) = (0..graph.len())
+        .filter(|&n| !removed[n])
+        .min_by_key(|&n| degree[n])
+    {
+        k = k.max(degree[n]);
+        removed[n] = true;
+        cores.insert(i_name[&n].clone(), k);
+        for &neigh in &graph[n] {
+            if !removed[neigh] {
+                degree[neigh] -= 1;
+            }
+        }
+    }
+    cores
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -166,4 +368,83 @@ td-yn"#;
     fn part2_example() {
         assert_eq!(part2(&parse(EXAMPLE)), "co,de,ka,ta");
     }
+
+    #[test]
+    fn k_cliques_example() {
+        let input = parse(EXAMPLE);
+        let triangles = k_cliques(&input, 3, &NameFilter::Any);
+        assert_eq!(triangles.len(), 12);
+        assert_eq!(triangles[0], ["aq", "cg", "yn"]);
+
+        let filter = NameFilter::Pattern("?a".to_string());
+        assert_eq!(k_cliques(&input, 4, &filter), [["co", "de", "ka", "ta"]]);
+
+        let filter = NameFilter::Set(["wh".to_string()].into_iter().collect());
+        assert_eq!(
+            k_cliques(&input, 3, &filter),
+            [["qp", "td", "wh"], ["tc", "td", "wh"], ["td", "wh", "yn"]]
+        );
+    }
+
+    #[test]
+    fn analysis_example() {
+        let input = parse(EXAMPLE);
+        let components = connected_components(&input);
+        assert_eq!(components.len(), 1);
+        assert_eq!(components[0].len(), 16);
+        assert_eq!(degree_distribution(&input), BTreeMap::from([(4, 16)]));
+        assert!(k_core_decomposition(&input).values().all(|&k| k == 4));
+
+        let input = parse("a-b\nb-c\nc-a\nd-c\ne-f\nf-g\ng-e");
+        assert_eq!(
+            connected_components(&input),
+            [vec!["a", "b", "c", "d"], vec!["e", "f", "g"]]
+        );
+        assert_eq!(
+            degree_distribution(&input),
+            BTreeMap::from([(1, 1), (2, 5), (3, 1)])
+        );
+        let cores = k_core_decomposition(&input);
+        assert_eq!(cores["d"], 1);
+        assert_eq!(cores["c"], 2);
+        assert_eq!(cores["g"], 2);
+
+        // links listed twice only count once
+        let input = parse("a-b\nb-a\na-c\nc-b\nb-c");
+        assert_eq!(k_cliques(&input, 3, &NameFilter::Any), [["a", "b", "c"]]);
+        assert_eq!(degree_distribution(&input), BTreeMap::from([(2, 3)]));
+        assert_eq!(to_dot(&input).matches(" -- ").count(), 3);
+
+        // `c` only appears on the right
+        let input = parse("a-b\nb-c");
+        assert_eq!(connected_components(&input), [["a", "b", "c"]]);
+        assert_eq!(
+            degree_distribution(&input),
+            BTreeMap::from([(1, 2), (2, 1)])
+        );
+    }
+
+    #[test]
+    fn export_example() {
+        let input = parse(EXAMPLE);
+        let dot = to_dot(&input);
+        assert!(dot.starts_with("graph lan {\n    \"aq\";\n"));
+        assert!(dot.contains("    \"co\" -- \"de\";\n"));
+        assert_eq!(dot.matches(" -- ").count(), 32);
+
+        let graphml = to_graphml(&input);
+        assert!(graphml.contains("<node id=\"wq\"/>"));
+        assert!(graphml.contains("<edge source=\"ka\" target=\"ta\"/>"));
+        assert_eq!(graphml.matches("<edge ").count(), 32);
+
+        let input = parse("1a-a&b\na\"b-1a");
+        assert_eq!(
+            to_dot(&input),
+            "graph lan {\n    \"1a\";\n    \"a\\\"b\";\n    \"a&b\";\n    \
+             \"1a\" -- \"a\\\"b\";\n    \"1a\" -- \"a&b\";\n}\n"
+        );
+        let graphml = to_graphml(&input);
+        assert!(graphml.contains("<node id=\"a&amp;b\"/>"));
+        assert!(graphml.contains("<edge source=\"1a\" target=\"a&quot;b\"/>"));
+    }
 }
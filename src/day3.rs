@@ -1,71 +1,272 @@
+use std::io::{self, BufRead};
+
 use aoc_runner_derive::{aoc, aoc_generator};
 
 type Input = Vec<Token>;
 
-#[derive(Debug)]
-pub enum Token {
-    Mul(u32, u32),
-    Do,
-    Dont,
+/// Instruction found in the corrupted memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    /// byte offset of the instruction in the input
+    pub offset: usize,
+    /// index of the instruction in its [`InstructionSet`]
+    pub instruction: usize,
+    pub args: Vec<u32>,
 }
 
-#[aoc_generator(day3)]
-pub fn parse(input: &str) -> Input {
-    let mut res = Vec::new();
-
-    let mut i = 0;
-    while i < input.len() {
-        let s = &input[i..];
-        if s.starts_with("mul(") {
-            i += 4;
-            let s = &input[i..];
-            let Some((content, _)) = s.split_once(")") else {
+/// State of the evaluation, updated by each instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Machine {
+    pub enabled: bool,
+    pub sum: u64,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Machine {
+            enabled: true,
+            sum: 0,
+        }
+    }
+}
+
+/// Instruction written as `name(a,b,...)`, with `arity` operands of 1 to 3 digits.
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub name: String,
+    pub arity: usize,
+    pub eval: fn(&mut Machine, &[u32]),
+}
+
+/// Instructions recognised by the tokenizer, `mul`, `do` and `don't` by default.
+#[derive(Debug, Clone)]
+pub struct InstructionSet {
+    instructions: Vec<Instruction>,
+}
+
+impl Default for InstructionSet {
+    fn default() -> Self {
+        InstructionSet::new()
+            .with("mul", 2, |m, args| {
+                if m.enabled {
+                    m.sum += args[0] as u64 * args[1] as u64;
+                }
+            })
+            .with("do", 0, |m, _| m.enabled = true)
+            .with("don't", 0, |m, _| m.enabled = false)
+    }
+}
+
+impl InstructionSet {
+    pub fn new() -> Self {
+        InstructionSet {
+            instructions: Vec::new(),
+        }
+    }
+
+    /// Registers an instruction, replacing the one with the same name if there is any.
+    pub fn with(mut self, name: &str, arity: usize, eval: fn(&mut Machine, &[u32])) -> Self {
+        let instruction = Instruction {
+            name: name.to_string(),
+            arity,
+            eval,
+        };
+        match self.instructions.iter_mut().find(|i| i.name == name) {
+            Some(i) => *i = instruction,
+            None => self.instructions.push(instruction),
+        }
+        self
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Matches an instruction at the start of `s`.
+    fn match_at(&self, s: &[u8]) -> Match {
+        'instr: for (ii, instr) in self.instructions.iter().enumerate() {
+            let name = instr.name.as_bytes();
+            if s.len() < name.len() {
+                if name.starts_with(s) {
+                    return Match::Partial;
+                }
                 continue;
+            }
+            let Some(rest) = s.strip_prefix(name) else {
+                continue;
+            };
+            let mut rest = match rest.first() {
+                Some(b'(') => &rest[1..],
+                Some(_) => continue,
+                None => return Match::Partial,
+            };
+            let mut args = Vec::with_capacity(instr.arity);
+            for ai in 0..instr.arity {
+                if ai > 0 {
+                    rest = match rest.first() {
+                        Some(b',') => &rest[1..],
+                        Some(_) => continue 'instr,
+                        None => return Match::Partial,
+                    };
+                }
+                let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+                if digits == rest.len() && digits <= 3 {
+                    // the number could go on
+                    return Match::Partial;
+                }
+                if !(1..=3).contains(&digits) {
+                    continue 'instr;
+                }
+                let val = rest[..digits]
+                    .iter()
+                    .fold(0, |acc, b| acc * 10 + (b - b'0') as u32);
+                args.push(val);
+                rest = &rest[digits..];
+            }
+            match rest.first() {
+                Some(b')') => return Match::Token(ii, args, s.len() - rest.len() + 1),
+                Some(_) => continue,
+                None => return Match::Partial,
+            }
+        }
+        Match::None
+    }
+
+    pub fn tokenize(&self, input: &str) -> Vec<Token> {
+        let input = input.as_bytes();
+        let mut res = Vec::new();
+        let mut i = 0;
+        while i < input.len() {
+            match self.match_at(&input[i..]) {
+                Match::Token(instruction, args, len) => {
+                    res.push(Token {
+                        offset: i,
+                        instruction,
+                        args,
+                    });
+                    i += len;
+                }
+                // nothing more can come after the end of the input
+                Match::Partial | Match::None => i += 1,
+            }
+        }
+        res
+    }
+
+    /// Tokenizes the input chunk by chunk, only keeping the end of the previous chunk when it
+    /// could be the start of an instruction.
+    pub fn tokenize_stream<R: BufRead>(&self, reader: R) -> TokenStream<'_, R> {
+        TokenStream {
+            set: self,
+            reader,
+            buf: Vec::new(),
+            start: 0,
+            offset: 0,
+            eof: false,
+        }
+    }
+
+    /// Evaluates the input in constant memory, without keeping the tokens.
+    pub fn evaluate_stream<R: BufRead>(&self, reader: R) -> io::Result<Machine> {
+        let mut machine = Machine::default();
+        for t in self.tokenize_stream(reader) {
+            let t = t?;
+            (self.instructions[t.instruction].eval)(&mut machine, &t.args);
+        }
+        Ok(machine)
+    }
+
+    /// Runs the tokens, which must come from this set or one with the same instruction order.
+    pub fn evaluate(&self, tokens: &[Token]) -> Machine {
+        let mut machine = Machine::default();
+        for t in tokens {
+            (self.instructions[t.instruction].eval)(&mut machine, &t.args);
+        }
+        machine
+    }
+}
+
+enum Match {
+    /// instruction index, operands and length
+    Token(usize, Vec<u32>, usize),
+    /// the input ended in the middle of what could be an instruction
+    Partial,
+    None,
+}
+
+/// Iterator over the tokens of a reader, see [`InstructionSet::tokenize_stream`].
+pub struct TokenStream<'a, R> {
+    set: &'a InstructionSet,
+    reader: R,
+    buf: Vec<u8>,
+    // index of the first byte not tokenized yet in `buf`
+    start: usize,
+    // offset of `buf[0]` in the whole input
+    offset: usize,
+    eof: bool,
+}
+
+impl<R: BufRead> Iterator for TokenStream<'_, R> {
+    type Item = io::Result<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while self.start < self.buf.len() {
+                match self.set.match_at(&self.buf[self.start..]) {
+                    Match::Token(instruction, args, len) => {
+                        let token = Token {
+                            offset: self.offset + self.start,
+                            instruction,
+                            args,
+                        };
+                        self.start += len;
+                        return Some(Ok(token));
+                    }
+                    Match::Partial if !self.eof => break,
+                    Match::Partial | Match::None => self.start += 1,
+                }
+            }
+            if self.eof {
+                return None;
+            }
+
+            // only keep the bytes that could start an instruction
+            self.buf.drain(..self.start);
+            self.offset += self.start;
+            self.start = 0;
+            let chunk = match self.reader.fill_buf() {
+                Ok(chunk) => chunk,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(e)),
             };
-            if let Some((a, b)) = content.split_once(",") {
-                let Ok(a) = a.parse() else {
-                    continue;
-                };
-                let Ok(b) = b.parse() else {
-                    continue;
-                };
-                i += content.len() + 1;
-                res.push(Token::Mul(a, b))
+            if chunk.is_empty() {
+                self.eof = true;
             }
-        } else if s.starts_with("do()") {
-            i += 4;
-            res.push(Token::Do);
-        } else if s.starts_with("don't()") {
-            i += 7;
-            res.push(Token::Dont);
-        } else {
-            i += 1;
+            self.buf.extend_from_slice(chunk);
+            let len = chunk.len();
+            self.reader.consume(len);
         }
     }
-    res
+}
+
+#[aoc_generator(day3)]
+pub fn parse(input: &str) -> Input {
+    InstructionSet::default().tokenize(input)
 }
 
 #[aoc(day3, part1)]
 pub fn part1(input: &Input) -> u32 {
-    input
-        .iter()
-        .map(|t| if let Token::Mul(a, b) = t { a * b } else { 0 })
-        .sum()
+    // the conditional instructions are ignored
+    InstructionSet::default()
+        .with("do", 0, |_, _| {})
+        .with("don't", 0, |_, _| {})
+        .evaluate(input)
+        .sum as u32
 }
 
 #[aoc(day3, part2)]
 pub fn part2(input: &Input) -> u32 {
-    let mut enabled = true;
-    let mut sum = 0;
-    for t in input {
-        match t {
-            Token::Do => enabled = true,
-            Token::Dont => enabled = false,
-            Token::Mul(a, b) if enabled => sum += a * b,
-            _ => {}
-        }
-    }
-    sum
+    InstructionSet::default().evaluate(input).sum as u32
 }
 
 #[cfg(test)]
@@ -86,4 +287,70 @@ mod test {
     fn part2_example() {
         assert_eq!(part2(&parse(EXAMPLE2)), 48);
     }
+
+    #[test]
+    fn strict_tokenizer() {
+        let tokens = parse("mul(+1,2)mul(1, 2)mul(1234,5)mul(12,345)xdo()don't()");
+        assert_eq!(
+            tokens,
+            [
+                Token {
+                    offset: 29,
+                    instruction: 0,
+                    args: vec![12, 345],
+                },
+                Token {
+                    offset: 41,
+                    instruction: 1,
+                    args: vec![],
+                },
+                Token {
+                    offset: 45,
+                    instruction: 2,
+                    args: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_instructions() {
+        let set = InstructionSet::default()
+            .with("add", 2, |m, args| {
+                if m.enabled {
+                    m.sum += (args[0] + args[1]) as u64
+                }
+            })
+            .with("reset", 0, |m, _| m.sum = 0);
+        let tokens = set.tokenize("mul(2,3)reset()add(1,2)mul(4,5)don't()add(7,7)add(1)");
+        assert_eq!(tokens.len(), 6);
+        assert_eq!(set.evaluate(&tokens).sum, 23);
+    }
+
+    #[test]
+    fn stream_chunks() {
+        let set = InstructionSet::default();
+        let input = EXAMPLE2.repeat(3);
+        let tokens = set.tokenize(&input);
+        // instructions straddle the chunk boundaries for every capacity
+        for capacity in 1..16 {
+            let reader = io::BufReader::with_capacity(capacity, input.as_bytes());
+            let streamed = set
+                .tokenize_stream(reader)
+                .collect::<io::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(streamed, tokens);
+        }
+        let reader = io::BufReader::with_capacity(4, EXAMPLE2.as_bytes());
+        assert_eq!(set.evaluate_stream(reader).unwrap().sum, 48);
+    }
+
+    #[test]
+    fn stream_large_sum() {
+        let set = InstructionSet::default();
+        let input = "mul(999,999)".repeat(5000);
+        let machine = set.evaluate_stream(input.as_bytes()).unwrap();
+        assert_eq!(machine.sum, 5000 * 999 * 999);
+        assert!(machine.sum > u32::MAX as u64);
+    }
 }
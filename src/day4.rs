@@ -1,82 +1,299 @@
+use std::collections::VecDeque;
+
 use aoc_runner_derive::{aoc, aoc_generator};
+use aoc_utils::{
+    grid::Grid,
+    point::{Point, DIAG, ORTHO},
+};
 
-type Input = (String, usize, usize);
+type Input = Grid<u8>;
 
 #[aoc_generator(day4)]
 pub fn parse(input: &str) -> Input {
-    let mut input = input.to_string();
-    // little trick to deal with the missing final newline
-    input.push('\n');
-    let width = input.lines().next().unwrap().len() + 1;
-    let height = input.lines().count();
-    (input, width, height)
+    Grid::parse(input)
 }
 
-#[aoc(day4, part1)]
-pub fn part1(input: &Input) -> u32 {
-    let word = b"XMAS";
-    let (input, width, height) = input;
-    let input = input.as_bytes();
-
-    const DIRECTIONS: [(isize, isize); 8] = [
-        (0, 1),
-        (1, 0),
-        (0, -1),
-        (-1, 0),
-        (1, 1),
-        (1, -1),
-        (-1, 1),
-        (-1, -1),
-    ];
-    let mut count = 0;
-    for ci in 0..input.len() {
-        if input[ci] == b'X' {
-            for direction in DIRECTIONS {
-                let mut row_i = (ci % width) as isize;
-                let mut col_i = (ci / width) as isize;
-                let mut wi = 0;
-                while wi < word.len()
-                    && col_i >= 0
-                    && col_i < *height as isize
-                    && row_i >= 0
-                    && row_i < *width as isize
-                    && input[col_i as usize * width + row_i as usize] == word[wi]
-                {
-                    col_i += direction.1;
-                    row_i += direction.0;
-                    wi += 1;
+/// The 8 directions a word can be written in.
+pub const DIRECTIONS: [Point; 8] = [
+    ORTHO[0], ORTHO[1], ORTHO[2], ORTHO[3], DIAG[0], DIAG[1], DIAG[2], DIAG[3],
+];
+
+/// Occurrence of a word, starting at `start` and going in `direction`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WordMatch {
+    /// index of the word in the searched list
+    pub word: usize,
+    pub start: Point,
+    pub direction: Point,
+}
+
+/// 2D pattern where `.` matches any letter.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Stencil {
+    width: usize,
+    height: usize,
+    cells: Vec<Option<u8>>,
+}
+
+impl Stencil {
+    /// Parses the ASCII art of the stencil, shorter lines are padded with wildcards.
+    pub fn parse(art: &str) -> Self {
+        let width = art.lines().map(|l| l.len()).max().unwrap_or(0);
+        let height = art.lines().count();
+        let mut cells = vec![None; width * height];
+        for (y, l) in art.lines().enumerate() {
+            for (x, b) in l.bytes().enumerate() {
+                if b != b'.' {
+                    cells[y * width + x] = Some(b);
                 }
-                if wi == word.len() {
-                    count += 1;
+            }
+        }
+        Stencil {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<u8> {
+        self.cells[y * self.width + x]
+    }
+
+    /// Rotates the stencil a quarter turn clockwise.
+    pub fn rotate(&self) -> Self {
+        let mut cells = Vec::with_capacity(self.cells.len());
+        for y in 0..self.width {
+            for x in 0..self.height {
+                cells.push(self.get(y, self.height - 1 - x));
+            }
+        }
+        Stencil {
+            width: self.height,
+            height: self.width,
+            cells,
+        }
+    }
+
+    /// Mirrors the stencil left to right.
+    pub fn reflect(&self) -> Self {
+        let mut cells = Vec::with_capacity(self.cells.len());
+        for y in 0..self.height {
+            for x in (0..self.width).rev() {
+                cells.push(self.get(x, y));
+            }
+        }
+        Stencil {
+            width: self.width,
+            height: self.height,
+            cells,
+        }
+    }
+
+    /// The distinct rotations and reflections of the stencil, starting with itself.
+    pub fn variants(&self) -> Vec<Stencil> {
+        let mut res: Vec<Stencil> = Vec::new();
+        let mut cur = self.clone();
+        for _ in 0..4 {
+            for s in [cur.reflect(), cur.clone()] {
+                if !res.contains(&s) {
+                    res.push(s);
                 }
             }
+            cur = cur.rotate();
         }
+        // keep the stencil itself first
+        let pos = res.iter().position(|s| s == self).unwrap();
+        res[..=pos].rotate_right(1);
+        res
     }
-    count
 }
 
-#[aoc(day4, part2)]
-pub fn part2(input: &Input) -> u32 {
-    let mut count = 0;
-    let (input, width, height) = input;
-    let input = input.as_bytes();
-    for ci in 0..input.len() {
-        let row_i = ci % width;
-        let col_i = ci / width;
-        if row_i > 0
-            && col_i > 0
-            && row_i < width - 1
-            && col_i < height - 1
-            && input[ci] == b'A'
-            && ([input[ci - width - 1], input[ci + width + 1]] == [b'M', b'S']
-                || [input[ci - width - 1], input[ci + width + 1]] == [b'S', b'M'])
-            && ([input[ci + width - 1], input[ci - width + 1]] == [b'M', b'S']
-                || [input[ci + width - 1], input[ci - width + 1]] == [b'S', b'M'])
-        {
-            count += 1;
+/// Occurrence of a stencil variant, with its top left corner at `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StencilMatch {
+    pub start: Point,
+    /// index in [`Stencil::variants`]
+    pub variant: usize,
+}
+
+/// Aho-Corasick automaton matching several words at once, with the transitions of every
+/// state precomputed so that each letter is a single lookup.
+#[derive(Debug, Clone)]
+pub struct Automaton {
+    // class of each byte, 0 for the ones in none of the words
+    classes: [u16; 256],
+    class_count: usize,
+    // transitions[state * class_count + class]
+    transitions: Vec<u32>,
+    // words ending at each state, including through the failure links
+    outputs: Vec<Vec<usize>>,
+    lens: Vec<usize>,
+}
+
+impl Automaton {
+    pub fn new(words: &[&str]) -> Self {
+        let mut classes = [0; 256];
+        let mut class_count = 1;
+        for b in words.iter().flat_map(|w| w.bytes()) {
+            if classes[b as usize] == 0 {
+                classes[b as usize] = class_count as u16;
+                class_count += 1;
+            }
         }
+
+        // trie, 0 is both the root and the missing transition
+        let mut transitions = vec![0; class_count];
+        let mut outputs = vec![Vec::new()];
+        for (wi, word) in words.iter().enumerate() {
+            if word.is_empty() {
+                continue;
+            }
+            let mut cur = 0;
+            for b in word.bytes() {
+                let ti = cur * class_count + classes[b as usize] as usize;
+                if transitions[ti] == 0 {
+                    transitions[ti] = outputs.len() as u32;
+                    transitions.extend(std::iter::repeat_n(0, class_count));
+                    outputs.push(Vec::new());
+                }
+                cur = transitions[ti] as usize;
+            }
+            outputs[cur].push(wi);
+        }
+
+        // breadth first so that the failure state is always complete before its use
+        let mut fail = vec![0; outputs.len()];
+        let mut queue = transitions[1..class_count]
+            .iter()
+            .filter(|&&next| next != 0)
+            .map(|&next| next as usize)
+            .collect::<VecDeque<_>>();
+        while let Some(state) = queue.pop_front() {
+            let inherited = outputs[fail[state]].clone();
+            outputs[state].extend(inherited);
+            for c in 1..class_count {
+                let ti = state * class_count + c;
+                let next = transitions[ti] as usize;
+                let fallback = transitions[fail[state] * class_count + c];
+                if next == 0 {
+                    transitions[ti] = fallback;
+                } else {
+                    fail[next] = fallback as usize;
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        Automaton {
+            classes,
+            class_count,
+            transitions,
+            outputs,
+            lens: words.iter().map(|w| w.len()).collect(),
+        }
+    }
+
+    fn step(&self, state: usize, b: u8) -> usize {
+        self.transitions[state * self.class_count + self.classes[b as usize] as usize] as usize
     }
-    count
+}
+
+/// Word search over the letters of the puzzle.
+pub struct WordSearch<'a> {
+    grid: &'a Grid<u8>,
+}
+
+impl<'a> WordSearch<'a> {
+    pub fn new(grid: &'a Grid<u8>) -> Self {
+        WordSearch { grid }
+    }
+
+    pub fn get(&self, p: Point) -> Option<u8> {
+        self.grid.contains(p).then(|| self.grid[p])
+    }
+
+    /// Neighbours of `p` inside the grid, with their letter.
+    pub fn neighbours(&self, p: Point) -> impl Iterator<Item = (Point, u8)> + '_ {
+        DIRECTIONS
+            .into_iter()
+            .filter_map(move |d| self.get(p + d).map(|b| (p + d, b)))
+    }
+
+    /// Cells from `start` to the edge of the grid, going in `direction`.
+    fn line(&self, start: Point, direction: Point) -> impl Iterator<Item = Point> + '_ {
+        std::iter::successors(Some(start), move |&p| Some(p + direction))
+            .take_while(|&p| self.grid.contains(p))
+    }
+
+    /// Every occurrence of the words in any of the 8 directions, in reading order of their
+    /// start, then in the order of the words and of [`DIRECTIONS`].
+    pub fn find_words(&self, words: &[&str]) -> Vec<WordMatch> {
+        let automaton = Automaton::new(words);
+        let mut res = Vec::new();
+        for (di, direction) in DIRECTIONS.into_iter().enumerate() {
+            // every line going in this direction starts right after the edge of the grid
+            let starts = (0..self.grid.bytes.len())
+                .map(|i| self.grid.as_point(i))
+                .filter(|&p| !self.grid.contains(p - direction));
+            for line_start in starts {
+                let mut state = 0;
+                for p in self.line(line_start, direction) {
+                    state = automaton.step(state, self.grid[p]);
+                    for &wi in &automaton.outputs[state] {
+                        let start = p - direction * (automaton.lens[wi] as i64 - 1);
+                        res.push((
+                            di,
+                            WordMatch {
+                                word: wi,
+                                start,
+                                direction,
+                            },
+                        ));
+                    }
+                }
+            }
+        }
+        res.sort_by_key(|(di, m)| (m.start.y, m.start.x, m.word, *di));
+        res.into_iter().map(|(_, m)| m).collect()
+    }
+
+    /// Every occurrence of the stencil, rotated or reflected, in reading order.
+    pub fn find_stencil(&self, stencil: &Stencil) -> Vec<StencilMatch> {
+        let mut res = Vec::new();
+        let variants = stencil.variants();
+        for i in 0..self.grid.bytes.len() {
+            let start = self.grid.as_point(i);
+            for (variant, v) in variants.iter().enumerate() {
+                let found = (0..v.height).all(|sy| {
+                    (0..v.width).all(|sx| {
+                        let p = start + Point::new(sx as i64, sy as i64);
+                        self.get(p)
+                            .is_some_and(|b| v.get(sx, sy).is_none_or(|s| s == b))
+                    })
+                });
+                if found {
+                    res.push(StencilMatch { start, variant });
+                }
+            }
+        }
+        res
+    }
+}
+
+const X_MAS: &str = "M.S
+.A.
+M.S";
+
+#[aoc(day4, part1)]
+pub fn part1(input: &Input) -> u32 {
+    WordSearch::new(input).find_words(&["XMAS"]).len() as u32
+}
+
+#[aoc(day4, part2)]
+pub fn part2(input: &Input) -> u32 {
+    WordSearch::new(input)
+        .find_stencil(&Stencil::parse(X_MAS))
+        .len() as u32
 }
 
 #[cfg(test)]
@@ -103,4 +320,117 @@ MXMXAXMASX"#;
     fn part2_example() {
         assert_eq!(part2(&parse(EXAMPLE)), 9);
     }
+
+    #[test]
+    fn stencil_variants() {
+        let stencil = Stencil::parse("AB\nC");
+        assert_eq!(stencil.get(1, 1), None);
+        assert_eq!(stencil.rotate(), Stencil::parse("CA\n.B"));
+        assert_eq!(stencil.reflect(), Stencil::parse("BA\n.C"));
+        let variants = stencil.variants();
+        assert_eq!(variants.len(), 8);
+        assert_eq!(variants[0], stencil);
+        assert_eq!(Stencil::parse(X_MAS).variants().len(), 4);
+        assert_eq!(Stencil::parse(".A.\nAAA\n.A.").variants().len(), 1);
+    }
+
+    /// Checks every cell and direction, letter by letter.
+    fn brute_force(grid: &Grid<u8>, words: &[&str]) -> Vec<WordMatch> {
+        let search = WordSearch::new(grid);
+        let mut res = Vec::new();
+        for i in 0..grid.bytes.len() {
+            let start = grid.as_point(i);
+            for (wi, word) in words.iter().enumerate() {
+                for direction in DIRECTIONS {
+                    let found = !word.is_empty()
+                        && word
+                            .bytes()
+                            .enumerate()
+                            .all(|(i, b)| search.get(start + direction * i as i64) == Some(b));
+                    if found {
+                        res.push(WordMatch {
+                            word: wi,
+                            start,
+                            direction,
+                        });
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn search_positions() {
+        let grid = parse("XMAS\n.A..\nS.S.");
+        let search = WordSearch::new(&grid);
+        let matches = search.find_words(&["XMAS", "SA"]);
+        assert_eq!(
+            matches,
+            [
+                WordMatch {
+                    word: 0,
+                    start: Point::new(0, 0),
+                    direction: Point::new(1, 0),
+                },
+                WordMatch {
+                    word: 1,
+                    start: Point::new(3, 0),
+                    direction: Point::new(-1, 0),
+                },
+                WordMatch {
+                    word: 1,
+                    start: Point::new(0, 2),
+                    direction: Point::new(1, -1),
+                },
+                WordMatch {
+                    word: 1,
+                    start: Point::new(2, 2),
+                    direction: Point::new(-1, -1),
+                },
+            ]
+        );
+        let matches = search.find_stencil(&Stencil::parse("X\n.\nS"));
+        assert_eq!(
+            matches,
+            [StencilMatch {
+                start: Point::new(0, 0),
+                variant: 0,
+            }]
+        );
+        assert_eq!(search.neighbours(Point::new(0, 0)).count(), 3);
+        assert_eq!(search.neighbours(Point::new(1, 1)).count(), 8);
+    }
+
+    #[test]
+    fn automaton_matches_brute_force() {
+        let grid = parse(EXAMPLE);
+        // overlapping words, words contained in others and palindromes
+        let words = ["XMAS", "MAS", "AS", "SAMX", "MM", "M", "", "AMA", "XMASAMX"];
+        assert_eq!(
+            WordSearch::new(&grid).find_words(&words),
+            brute_force(&grid, &words)
+        );
+    }
+
+    #[test]
+    fn automaton_many_bytes() {
+        // every byte a string can hold except 0, one class each
+        let text = (1..0x800)
+            .chain((0..16).map(|i| (i * 0x1000).max(0x800)))
+            .chain((0..5).map(|i| (i * 0x40000).max(0x10000)))
+            .filter_map(char::from_u32)
+            .collect::<String>();
+        let words = [text.as_str(), "\u{7ff}\u{1}"];
+        let automaton = Automaton::new(&words);
+        assert_eq!(automaton.class_count, 243);
+        let mut state = 0;
+        let mut found = Vec::<usize>::new();
+        for b in text.bytes().chain("\u{7ff}\u{1}".bytes()) {
+            state = automaton.step(state, b);
+            found.extend(&automaton.outputs[state]);
+        }
+        assert_eq!(found, [0, 1]);
+        assert_eq!(automaton.step(state, 0), 0);
+    }
 }
@@ -1,18 +1,279 @@
-use gxhash::{HashMap, HashMapExt};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BTreeSet,
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
 
 use aoc_runner_derive::{aoc, aoc_generator};
+use gxhash::{HashMap, HashMapExt};
+
+type Input = (Rules, Vec<Vec<usize>>);
+
+/// Page ordering rules compiled into a precedence matrix over the pages found in the rules:
+/// bit `after` of row `before` is set for each `before|after` rule, with the pages replaced by
+/// their index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rules {
+    index: HashMap<usize, usize>,
+    words: usize,
+    bits: Vec<u64>,
+}
+
+/// The rules restricted to an update contain a cycle, so the pages can't be ordered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleError {
+    /// pages on the cycle, each one having to be printed before the next one
+    pub pages: Vec<usize>,
+}
+
+impl Display for CycleError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "cyclic ordering rules between pages ")?;
+        for page in &self.pages {
+            write!(f, "{page}|")?;
+        }
+        write!(f, "{}", self.pages[0])
+    }
+}
+
+impl Error for CycleError {}
+
+/// A `before|after` rule broken by an update, with the positions of both pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Violation {
+    pub before: usize,
+    pub after: usize,
+    pub before_pos: usize,
+    pub after_pos: usize,
+}
+
+/// Takes the page at index `from` out of the update and inserts it back at index `to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub page: usize,
+    pub from: usize,
+    pub to: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub violations: Vec<Violation>,
+    /// moves to apply in order to fix the update
+    pub moves: Vec<Move>,
+}
+
+impl Rules {
+    pub fn new(rules: &[(usize, usize)]) -> Self {
+        let mut index = HashMap::new();
+        for &(before, after) in rules {
+            for page in [before, after] {
+                let next = index.len();
+                index.entry(page).or_insert(next);
+            }
+        }
+        let size = index.len();
+        let words = size.div_ceil(64);
+        let mut bits = vec![0; size * words];
+        for &(before, after) in rules {
+            let (before, after) = (index[&before], index[&after]);
+            bits[before * words + after / 64] |= 1 << (after % 64);
+        }
+        Rules { index, words, bits }
+    }
+
+    /// Whether a rule requires `before` to be printed before `after`.
+    pub fn precedes(&self, before: usize, after: usize) -> bool {
+        let (Some(&before), Some(&after)) = (self.index.get(&before), self.index.get(&after))
+        else {
+            return false;
+        };
+        (self.bits[before * self.words + after / 64] >> (after % 64)) & 1 == 1
+    }
+
+    pub fn compare(&self, a: usize, b: usize) -> Ordering {
+        if self.precedes(a, b) {
+            Ordering::Less
+        } else if self.precedes(b, a) {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    }
+
+    pub fn is_ordered(&self, update: &[usize]) -> bool {
+        update
+            .iter()
+            .enumerate()
+            .all(|(i, &a)| update[i + 1..].iter().all(|&b| !self.precedes(b, a)))
+    }
+
+    /// Sorts the pages with the rules as comparator when they totally order the pages, and
+    /// falls back to [`Rules::topo_sort`] otherwise.
+    pub fn sort(&self, update: &mut [usize]) -> Result<(), CycleError> {
+        // the rules are a total order when every pair is ordered and the pages all have a
+        // different number of predecessors, otherwise there is a cycle of 3 pages
+        let n = update.len();
+        let mut predecessors = vec![0; n];
+        let mut total = true;
+        for i in 0..n {
+            for j in i + 1..n {
+                match self.compare(update[i], update[j]) {
+                    Ordering::Less => predecessors[j] += 1,
+                    Ordering::Greater => predecessors[i] += 1,
+                    Ordering::Equal => total = false,
+                }
+            }
+        }
+        if total {
+            let mut seen = vec![false; n];
+            total = predecessors
+                .iter()
+                .all(|&p| !std::mem::replace(&mut seen[p], true));
+        }
+        if total {
+            update.sort_by(|&a, &b| self.compare(a, b));
+        } else {
+            let sorted = self.topo_sort(update)?;
+            update.copy_from_slice(&sorted);
+        }
+        Ok(())
+    }
+
+    /// Orders the pages with Kahn's algorithm on the rules restricted to the update, taking the
+    /// earliest page of the update first when several are possible.
+    pub fn topo_sort(&self, update: &[usize]) -> Result<Vec<usize>, CycleError> {
+        let n = update.len();
+        let mut in_degree = (0..n)
+            .map(|i| {
+                (0..n)
+                    .filter(|&j| self.precedes(update[j], update[i]))
+                    .count()
+            })
+            .collect::<Vec<_>>();
+        let mut ready = (0..n)
+            .filter(|&i| in_degree[i] == 0)
+            .collect::<BTreeSet<_>>();
+        let mut done = vec![false; n];
+        let mut res = Vec::with_capacity(n);
+        while let Some(i) = ready.pop_first() {
+            done[i] = true;
+            res.push(update[i]);
+            for j in 0..n {
+                if self.precedes(update[i], update[j]) {
+                    in_degree[j] -= 1;
+                    if in_degree[j] == 0 {
+                        ready.insert(j);
+                    }
+                }
+            }
+        }
+        if res.len() == n {
+            return Ok(res);
+        }
+
+        // every remaining page has a remaining predecessor, walk them back until one repeats
+        let mut path = vec![(0..n).find(|&i| !done[i]).unwrap()];
+        loop {
+            let cur = *path.last().unwrap();
+            let prev = (0..n)
+                .find(|&j| !done[j] && self.precedes(update[j], update[cur]))
+                .unwrap();
+            if let Some(start) = path.iter().position(|&i| i == prev) {
+                let mut pages = path[start..].iter().map(|&i| update[i]).collect::<Vec<_>>();
+                pages.reverse();
+                return Err(CycleError { pages });
+            }
+            path.push(prev);
+        }
+    }
+
+    pub fn violations(&self, update: &[usize]) -> Vec<Violation> {
+        let mut res = Vec::new();
+        for (after_pos, &after) in update.iter().enumerate() {
+            for (before_pos, &before) in update.iter().enumerate().skip(after_pos + 1) {
+                if self.precedes(before, after) {
+                    res.push(Violation {
+                        before,
+                        after,
+                        before_pos,
+                        after_pos,
+                    });
+                }
+            }
+        }
+        res
+    }
+
+    /// Fewest page moves turning the update into its topological order: the pages on a longest
+    /// subsequence already in that order stay, the others are moved right after the page
+    /// preceding them.
+    /// This is the minimal fix when the rules totally order the pages, otherwise other valid
+    /// orders could be reachable with fewer moves.
+    pub fn fix_moves(&self, update: &[usize]) -> Result<Vec<Move>, CycleError> {
+        let target = self.topo_sort(update)?;
+        let rank = |page: usize| target.iter().position(|&p| p == page).unwrap();
+        let ranks = update.iter().map(|&p| rank(p)).collect::<Vec<_>>();
+
+        // longest increasing subsequence of the ranks
+        let mut lengths = vec![1; ranks.len()];
+        let mut prev = vec![None; ranks.len()];
+        for i in 0..ranks.len() {
+            for j in 0..i {
+                if ranks[j] < ranks[i] && lengths[j] + 1 > lengths[i] {
+                    lengths[i] = lengths[j] + 1;
+                    prev[i] = Some(j);
+                }
+            }
+        }
+        let mut kept = vec![false; target.len()];
+        let mut cur = (0..ranks.len()).max_by_key(|&i| (lengths[i], Reverse(i)));
+        while let Some(i) = cur {
+            kept[ranks[i]] = true;
+            cur = prev[i];
+        }
+
+        let mut pages = update.to_vec();
+        let mut moves = Vec::new();
+        for (t, &page) in target.iter().enumerate() {
+            if kept[t] {
+                continue;
+            }
+            let from = pages.iter().position(|&p| p == page).unwrap();
+            pages.remove(from);
+            let to = match t {
+                0 => 0,
+                _ => pages.iter().position(|&p| p == target[t - 1]).unwrap() + 1,
+            };
+            pages.insert(to, page);
+            moves.push(Move { page, from, to });
+        }
+        Ok(moves)
+    }
+
+    pub fn explain(&self, update: &[usize]) -> Result<Explanation, CycleError> {
+        Ok(Explanation {
+            violations: self.violations(update),
+            moves: self.fix_moves(update)?,
+        })
+    }
+}
 
-type Input = (HashMap<usize, Vec<usize>>, Vec<Vec<usize>>);
+/// Explains, for each update, which rules it breaks and how to fix it.
+pub fn explain(input: &Input) -> Vec<Result<Explanation, CycleError>> {
+    let (rules, lists) = input;
+    lists.iter().map(|list| rules.explain(list)).collect()
+}
 
 #[aoc_generator(day5)]
 pub fn parse(input: &str) -> Input {
     let (orders, lists) = input.split_once("\n\n").unwrap();
-    let mut h: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut rules = Vec::new();
     for l in orders.lines() {
         let (key, value) = l.split_once("|").unwrap();
         let key = key.parse().unwrap();
         let value = value.parse().unwrap();
-        h.entry(key).or_default().push(value);
+        rules.push((key, value));
     }
     let lists = lists
         .lines()
@@ -22,53 +283,27 @@ pub fn parse(input: &str) -> Input {
                 .collect()
         })
         .collect();
-    (h, lists)
+    (Rules::new(&rules), lists)
 }
 
 #[aoc(day5, part1)]
 pub fn part1(input: &Input) -> usize {
-    let (orders, lists) = input;
-    let mut total = 0;
-    'list: for list in lists {
-        for (i, val) in list.iter().enumerate() {
-            if let Some(poss) = orders.get(val) {
-                // check all values before to see if invalid
-                for val2 in list.iter().take(i) {
-                    if poss.contains(val2) {
-                        continue 'list;
-                    }
-                }
-            }
-        }
-        total += list[list.len() / 2];
-    }
-    total
+    let (rules, lists) = input;
+    lists
+        .iter()
+        .filter(|list| rules.is_ordered(list))
+        .map(|list| list[list.len() / 2])
+        .sum()
 }
 
 #[aoc(day5, part2)]
 pub fn part2(input: &Input) -> usize {
-    let (orders, lists) = input;
-    let lists = lists.clone();
+    let (rules, lists) = input;
     let mut total = 0;
-    for mut list in lists {
-        let mut invalid = false;
-        // unoptimized bubble sort
-        let mut swap = true;
-        while swap {
-            swap = false;
-            for i in 0..list.len() {
-                if let Some(poss) = orders.get(&list[i]) {
-                    for j in 0..i {
-                        if poss.contains(&list[j]) {
-                            invalid = true;
-                            swap = true;
-                            list.swap(j, i);
-                        }
-                    }
-                }
-            }
-        }
-        if invalid {
+    for list in lists {
+        if !rules.is_ordered(list) {
+            let mut list = list.clone();
+            rules.sort(&mut list).unwrap();
             total += list[list.len() / 2];
         }
     }
@@ -117,4 +352,99 @@ mod test {
     fn part2_example() {
         assert_eq!(part2(&parse(EXAMPLE)), 123);
     }
+
+    #[test]
+    fn topo_sort_example() {
+        let (rules, _) = parse(EXAMPLE);
+        assert_eq!(
+            rules.topo_sort(&[97, 13, 75, 29, 47]),
+            Ok(vec![97, 75, 47, 29, 13])
+        );
+
+        let rules = Rules::new(&[(1, 2), (2, 3), (3, 1), (3, 4), (5, 1)]);
+        let err = rules.topo_sort(&[4, 3, 5, 2, 1]).unwrap_err();
+        assert_eq!(err.pages, [1, 2, 3]);
+        assert_eq!(
+            err.to_string(),
+            "cyclic ordering rules between pages 1|2|3|1"
+        );
+        // the cycle isn't part of the update
+        assert_eq!(rules.topo_sort(&[1, 2, 4]), Ok(vec![1, 2, 4]));
+    }
+
+    #[test]
+    fn sort_partial_order() {
+        // a page goes before its multiples, most pairs aren't ordered
+        let rules = (1..=30)
+            .flat_map(|a| (2..=30 / a).map(move |k| (a, a * k)))
+            .collect::<Vec<_>>();
+        let rules = Rules::new(&rules);
+        let mut update = (1..=30).rev().collect::<Vec<_>>();
+        rules.sort(&mut update).unwrap();
+        assert!(rules.is_ordered(&update));
+        assert_eq!(update.iter().sum::<usize>(), 465);
+
+        let (rules, _) = parse(EXAMPLE);
+        let mut update = vec![97, 13, 75, 29, 47];
+        rules.sort(&mut update).unwrap();
+        assert_eq!(update, [97, 75, 47, 29, 13]);
+
+        // every pair is ordered, but in a cycle
+        let rules = Rules::new(&[(1, 2), (2, 3), (3, 1)]);
+        assert_eq!(rules.sort(&mut [1, 2, 3]).unwrap_err().pages, [2, 3, 1]);
+    }
+
+    #[test]
+    fn sparse_pages() {
+        // the matrix only covers the pages found in the rules
+        let rules = Rules::new(&[(1, 3_000_000), (3_000_000, usize::MAX)]);
+        assert!(rules.precedes(1, 3_000_000));
+        assert!(!rules.precedes(3_000_000, 1));
+        assert!(!rules.precedes(1, usize::MAX));
+        assert!(!rules.precedes(2, 3_000_000));
+        let mut update = vec![usize::MAX, 7, 3_000_000, 1];
+        rules.sort(&mut update).unwrap();
+        assert_eq!(update, [7, 1, 3_000_000, usize::MAX]);
+    }
+
+    #[test]
+    fn explain_example() {
+        let input = parse(EXAMPLE);
+        let explanations = explain(&input);
+        assert!(explanations[..3].iter().all(|e| e.as_ref().unwrap()
+            == &Explanation {
+                violations: vec![],
+                moves: vec![],
+            }));
+
+        let e = explanations[3].as_ref().unwrap();
+        assert_eq!(
+            e.violations,
+            [Violation {
+                before: 97,
+                after: 75,
+                before_pos: 1,
+                after_pos: 0,
+            }]
+        );
+        assert_eq!(
+            e.moves,
+            [Move {
+                page: 97,
+                from: 1,
+                to: 0,
+            }]
+        );
+
+        // 97,13,75,29,47 -> 97,75,47,29,13
+        let e = explanations[5].as_ref().unwrap();
+        assert_eq!(e.violations.len(), 4);
+        let mut pages = input.1[5].clone();
+        for m in &e.moves {
+            assert_eq!(pages.remove(m.from), m.page);
+            pages.insert(m.to, m.page);
+        }
+        assert_eq!(pages, [97, 75, 47, 29, 13]);
+        assert_eq!(e.moves.len(), 2);
+    }
 }
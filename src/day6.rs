@@ -4,26 +4,16 @@ use aoc_runner_derive::aoc;
 
 type Input = (usize, usize, usize, usize, Vec<u8>);
 type InputRef<'a> = (usize, usize, usize, usize, &'a [u8]);
-const DIRECTIONS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+/// up, right, down, left
+pub const DIRECTIONS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+const GUARDS: [u8; 4] = [b'^', b'>', b'v', b'<'];
 
 pub fn parse(input: &str) -> Input {
     let width = input.lines().next().unwrap().len() + 1;
     let height = input.lines().count();
     let input = input.as_bytes();
-    let mut gi = 0;
-    for (ci, &c) in input.iter().enumerate() {
-        if c == b'^' {
-            gi = ci;
-            break;
-        }
-    }
-    let gd = match input[gi] {
-        b'^' => 0,
-        b'>' => 1,
-        b'v' => 2,
-        b'<' => 3,
-        _ => unreachable!(),
-    };
+    let gi = input.iter().position(|c| GUARDS.contains(c)).unwrap();
+    let gd = GUARDS.iter().position(|&c| c == input[gi]).unwrap();
     let mut input = input.to_vec();
     input[gi] = b'.';
     (width, height, gi, gd, input)
@@ -37,9 +27,11 @@ fn idx(width: usize, x: i32, y: i32) -> usize {
     x as usize + y as usize * width
 }
 
+/// Tiles visited by the guard, until it leaves the map or turns at the same place twice.
 fn guard_run(input: &InputRef) -> HashSet<(i32, i32)> {
     let &(ref width, _, gi, mut gd, input) = input;
     let mut visited = HashSet::new();
+    let mut turns = HashSet::new();
     let mut x = (gi % width) as i32;
     let mut y = (gi / width) as i32;
 
@@ -56,6 +48,10 @@ fn guard_run(input: &InputRef) -> HashSet<(i32, i32)> {
 
         match input.get(idx(*width, x + dir.0, y + dir.1)) {
             Some(b'#') => {
+                visited.insert((x, y));
+                if !turns.insert((x, y, gd)) {
+                    break;
+                }
                 gd = (gd + 1) % 4;
             }
             Some(b'\n') | None => {
@@ -70,7 +66,20 @@ fn guard_run(input: &InputRef) -> HashSet<(i32, i32)> {
     visited
 }
 
-fn guard_loop(input: &InputRef) -> bool {
+/// Cycle the guard gets stuck in, a step being a straight run followed by a turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopInfo {
+    /// number of steps before entering the cycle
+    pub start: usize,
+    /// number of steps of the cycle
+    pub length: usize,
+    /// `(x, y, dir)` states after each step of the cycle, `dir` being an index in [`DIRECTIONS`]
+    pub states: HashSet<(i32, i32, usize)>,
+}
+
+/// Tortoise and hare simulation of the whole walk, returning the cycle if the guard never
+/// leaves the map.
+fn guard_loop(input: &InputRef, turn: Turn) -> Option<LoopInfo> {
     let (width, _, gi, gd, input) = input;
     #[derive(PartialEq, Eq, Clone, Copy)]
     struct State(i32, i32, usize);
@@ -88,7 +97,7 @@ fn guard_loop(input: &InputRef) -> bool {
 
         match input.get(idx(*width, state.0 + dir.0, state.1 + dir.1)) {
             Some(b'#') => {
-                state.2 = (state.2 + 1) % 4;
+                state.2 = turn.apply(state.2);
             }
             Some(b'\n') | None => {
                 return true;
@@ -100,20 +109,276 @@ fn guard_loop(input: &InputRef) -> bool {
         false
     };
 
-    let mut tortoise = State((gi % width) as i32, (gi / width) as i32, *gd);
+    let start = State((gi % width) as i32, (gi / width) as i32, *gd);
+    let mut tortoise = start;
     let mut hare = tortoise;
 
     loop {
         step(&mut tortoise);
         if step(&mut hare) {
-            return false;
+            return None;
         }
         if step(&mut hare) {
-            return false;
+            return None;
         }
         if tortoise == hare {
-            return true;
+            break;
+        }
+    }
+
+    // the distance to the start of the cycle is the same from the start and the meeting point
+    let mut mu = 0;
+    tortoise = start;
+    while tortoise != hare {
+        step(&mut tortoise);
+        step(&mut hare);
+        mu += 1;
+    }
+
+    let mut states = HashSet::new();
+    states.insert((tortoise.0, tortoise.1, tortoise.2));
+    let mut lambda = 1;
+    hare = tortoise;
+    step(&mut hare);
+    while tortoise != hare {
+        states.insert((hare.0, hare.1, hare.2));
+        step(&mut hare);
+        lambda += 1;
+    }
+
+    Some(LoopInfo {
+        start: mu,
+        length: lambda,
+        states,
+    })
+}
+
+const EXIT: u32 = u32::MAX;
+
+/// For each cell and direction, the cell where the guard stops in front of the next obstacle,
+/// or [`EXIT`] if it leaves the map.
+struct JumpTable {
+    width: usize,
+    jumps: Vec<u32>,
+}
+
+impl JumpTable {
+    fn new(input: &InputRef) -> Self {
+        let &(width, _, _, _, input) = input;
+        let mut jumps = vec![EXIT; input.len() * 4];
+        for (dir, &(dx, dy)) in DIRECTIONS.iter().enumerate() {
+            // the jump of a cell is the one of the next cell, so compute the next one first
+            let order: Box<dyn Iterator<Item = usize>> = if dx + dy < 0 {
+                Box::new(0..input.len())
+            } else {
+                Box::new((0..input.len()).rev())
+            };
+            for ci in order {
+                let (x, y) = ((ci % width) as i32, (ci / width) as i32);
+                let next = idx(width, x + dx, y + dy);
+                jumps[ci * 4 + dir] = match input.get(next) {
+                    Some(b'#') => ci as u32,
+                    Some(b'\n') | None => EXIT,
+                    Some(_) => jumps[next * 4 + dir],
+                };
+            }
+        }
+        JumpTable { width, jumps }
+    }
+
+    /// Patches the cells stopped by an obstacle added at `(ox, oy)`, returning the previous
+    /// entries to restore it afterwards.
+    fn insert_obstacle(&mut self, input: &[u8], ox: i32, oy: i32) -> Vec<(usize, u32)> {
+        let mut undo = Vec::new();
+        for (dir, &(dx, dy)) in DIRECTIONS.iter().enumerate() {
+            let stop = idx(self.width, ox - dx, oy - dy);
+            if input.get(stop).is_none_or(|&c| c == b'\n') {
+                continue;
+            }
+            let (mut x, mut y) = (ox - dx, oy - dy);
+            while let Some(&c) = input.get(idx(self.width, x, y)) {
+                if c == b'#' || c == b'\n' {
+                    break;
+                }
+                let ji = idx(self.width, x, y) * 4 + dir;
+                undo.push((ji, self.jumps[ji]));
+                self.jumps[ji] = stop as u32;
+                x -= dx;
+                y -= dy;
+            }
+        }
+        undo
+    }
+
+    fn restore(&mut self, undo: Vec<(usize, u32)>) {
+        for (ji, jump) in undo.into_iter().rev() {
+            self.jumps[ji] = jump;
+        }
+    }
+
+    /// Follows the jumps from the start, only visiting the turning points.
+    /// `seen` holds the last `stamp` each state was visited with, to avoid clearing it.
+    fn is_loop(&self, gi: usize, gd: usize, turn: Turn, seen: &mut [u32], stamp: u32) -> bool {
+        let (mut ci, mut dir) = (gi, gd);
+        loop {
+            let next = self.jumps[ci * 4 + dir];
+            if next == EXIT {
+                return false;
+            }
+            ci = next as usize;
+            dir = turn.apply(dir);
+            if seen[ci * 4 + dir] == stamp {
+                return true;
+            }
+            seen[ci * 4 + dir] = stamp;
+        }
+    }
+}
+
+/// What the guard does when facing an obstacle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Turn {
+    #[default]
+    Right,
+    Left,
+    Reverse,
+}
+
+impl Turn {
+    fn apply(self, dir: usize) -> usize {
+        match self {
+            Turn::Right => (dir + 1) % 4,
+            Turn::Left => (dir + 3) % 4,
+            Turn::Reverse => (dir + 2) % 4,
+        }
+    }
+}
+
+/// Guard patrol on a map, with a configurable turning behaviour.
+#[derive(Debug, Clone)]
+pub struct Patrol {
+    input: Input,
+    turn: Turn,
+}
+
+impl Patrol {
+    pub fn new(input: &str) -> Self {
+        Patrol {
+            input: parse(input),
+            turn: Turn::default(),
+        }
+    }
+
+    pub fn with_turn(mut self, turn: Turn) -> Self {
+        self.turn = turn;
+        self
+    }
+
+    /// Ordered `(x, y, dir)` states of the guard, `dir` being an index in [`DIRECTIONS`].
+    /// A new state is recorded for every move and every turn, until the guard leaves the map
+    /// or gets back to a state already visited.
+    pub fn trajectory(&self) -> Vec<(i32, i32, usize)> {
+        let (width, _, gi, gd, ref input) = self.input;
+        let mut state = ((gi % width) as i32, (gi / width) as i32, gd);
+        let mut seen = HashSet::new();
+        let mut trajectory = Vec::new();
+        while seen.insert(state) {
+            trajectory.push(state);
+            let (x, y, dir) = state;
+            let (dx, dy) = DIRECTIONS[dir];
+            match input.get(idx(width, x + dx, y + dy)) {
+                Some(b'#') => state.2 = self.turn.apply(dir),
+                Some(b'\n') | None => break,
+                Some(_) => state = (x + dx, y + dy, dir),
+            }
+        }
+        trajectory
+    }
+
+    /// The cycle the guard gets stuck in, if it never leaves the map.
+    pub fn guard_loop(&self) -> Option<LoopInfo> {
+        let (width, height, gi, gd, ref input) = self.input;
+        guard_loop(&(width, height, gi, gd, input), self.turn)
+    }
+
+    /// Positions where adding an obstacle traps the guard in a loop, sorted by row then column.
+    pub fn loop_positions(&self) -> Vec<(i32, i32)> {
+        let (width, height, gi, gd, ref input) = self.input;
+        let mut table = JumpTable::new(&(width, height, gi, gd, input));
+        let mut seen = vec![0; input.len() * 4];
+        // only the tiles on the original path can change the walk
+        let path = self
+            .trajectory()
+            .into_iter()
+            .map(|(x, y, _)| (x, y))
+            .collect::<HashSet<_>>();
+        let mut res = Vec::new();
+        for (stamp, (x, y)) in (1..).zip(path) {
+            if idx(width, x, y) == gi {
+                continue;
+            }
+            let undo = table.insert_obstacle(input, x, y);
+            if table.is_loop(gi, gd, self.turn, &mut seen, stamp) {
+                res.push((x, y));
+            }
+            table.restore(undo);
+        }
+        res.sort_by_key(|&(x, y)| (y, x));
+        res
+    }
+
+    /// Positions where adding an obstacle traps the guard in a loop, with that loop, sorted by
+    /// row then column.
+    pub fn loop_obstacles(&self) -> Vec<((i32, i32), LoopInfo)> {
+        let (width, height, gi, gd, ref input) = self.input;
+        let mut input = input.clone();
+        self.loop_positions()
+            .into_iter()
+            .map(|(x, y)| {
+                let ci = idx(width, x, y);
+                input[ci] = b'#';
+                let info = guard_loop(&(width, height, gi, gd, &input), self.turn).unwrap();
+                input[ci] = b'.';
+                ((x, y), info)
+            })
+            .collect()
+    }
+
+    /// Renders the map with the path of the guard like in the puzzle statement: `|` and `-`
+    /// for vertical and horizontal moves, `+` where the guard turned or crossed its path.
+    pub fn render(&self) -> String {
+        let (width, _, gi, gd, ref input) = self.input;
+        const VERTICAL: u8 = 1;
+        const HORIZONTAL: u8 = 2;
+        let mut marks = vec![0u8; input.len()];
+        let trajectory = self.trajectory();
+        for w in trajectory.windows(2) {
+            let ((x1, y1, d1), (x2, y2, d2)) = (w[0], w[1]);
+            let (i1, i2) = (idx(width, x1, y1), idx(width, x2, y2));
+            if d1 != d2 {
+                marks[i1] |= VERTICAL | HORIZONTAL;
+            } else {
+                let axis = if d1 % 2 == 0 { VERTICAL } else { HORIZONTAL };
+                marks[i1] |= axis;
+                marks[i2] |= axis;
+            }
+        }
+        if let Some(&(x, y, d)) = trajectory.last() {
+            marks[idx(width, x, y)] |= if d % 2 == 0 { VERTICAL } else { HORIZONTAL };
         }
+
+        let mut out = input
+            .iter()
+            .zip(&marks)
+            .map(|(&c, &m)| match m {
+                VERTICAL => '|',
+                HORIZONTAL => '-',
+                0 => c as char,
+                _ => '+',
+            })
+            .collect::<String>();
+        out.replace_range(gi..gi + 1, &(GUARDS[gd] as char).to_string());
+        out
     }
 }
 
@@ -127,31 +392,13 @@ pub fn part1(input: &str) -> u32 {
 
 #[aoc(day6, part2)]
 pub fn part2(input: &str) -> u32 {
-    let input = parse(input);
-    let (width, height, gi, gd, mut input) = input;
-    let visited = guard_run(&(width, height, gi, gd, &input));
-    let mut count = 0;
-    let mut old = None;
-    for (base_x, base_y) in visited {
-        let ngi = idx(width, base_x, base_y);
-        if ngi == gi {
-            continue;
-        }
-        if let Some(old) = old {
-            input[old] = b'.';
-        }
-        input[ngi] = b'#';
-        let l = guard_loop(&(width, height, gi, gd, &input));
-        if l {
-            count += 1;
-        }
-        old = Some(ngi);
-    }
-    count
+    Patrol::new(input).loop_positions().len() as u32
 }
 
 #[cfg(test)]
 mod test {
+    use itertools::Itertools;
+
     use super::*;
 
     const EXAMPLE: &str = r#"....#.....
@@ -174,4 +421,101 @@ mod test {
     fn part2_example() {
         assert_eq!(part2(EXAMPLE), 6);
     }
+
+    #[test]
+    fn patrol_render() {
+        let patrol = Patrol::new(
+            "#....\n\
+             ....#\n\
+             .....\n\
+             <..#.",
+        );
+        assert_eq!(patrol.trajectory().len(), 1);
+        assert_eq!(patrol.render(), "#....\n....#\n.....\n<..#.");
+
+        let patrol = Patrol::new(
+            ".#...\n\
+             ....#\n\
+             .....\n\
+             .^.#.",
+        );
+        assert_eq!(
+            patrol.trajectory()[..5],
+            [(1, 3, 0), (1, 2, 0), (1, 1, 0), (1, 1, 1), (2, 1, 1)]
+        );
+        assert_eq!(patrol.render(), ".#...\n.+-+#\n-+-+.\n.^.#.");
+        assert_eq!(
+            patrol.with_turn(Turn::Reverse).render(),
+            ".#...\n.+..#\n.|...\n.^.#."
+        );
+    }
+
+    #[test]
+    fn loop_positions_example() {
+        assert_eq!(
+            Patrol::new(EXAMPLE).loop_positions(),
+            [(3, 6), (6, 7), (3, 8), (1, 8), (7, 7), (7, 9)]
+                .into_iter()
+                .sorted_by_key(|&(x, y)| (y, x))
+                .collect::<Vec<_>>()
+        );
+
+        // cross-check against the full simulation
+        for turn in [Turn::Right, Turn::Left, Turn::Reverse] {
+            let patrol = Patrol::new(EXAMPLE).with_turn(turn);
+            let positions = patrol.loop_positions();
+            let (width, height, gi, gd, mut input) = parse(EXAMPLE);
+            for ci in 0..input.len() {
+                if ci == gi || input[ci] != b'.' {
+                    continue;
+                }
+                input[ci] = b'#';
+                let is_loop = guard_loop(&(width, height, gi, gd, &input), turn).is_some();
+                let (x, y) = ((ci % width) as i32, (ci / width) as i32);
+                assert_eq!(is_loop, positions.contains(&(x, y)), "{turn:?} {x},{y}");
+                input[ci] = b'.';
+            }
+        }
+    }
+
+    #[test]
+    fn already_looping() {
+        let input = ".#..\n...#\n#^..\n..#.";
+        assert!(Patrol::new(input).guard_loop().is_some());
+        assert_eq!(part1(input), 4);
+        // any obstacle on the cycle sends the guard out of the map
+        assert_eq!(part2(input), 0);
+        assert!(Patrol::new(input).loop_obstacles().is_empty());
+    }
+
+    #[test]
+    fn guard_loop_info() {
+        let patrol = Patrol::new(EXAMPLE);
+        assert_eq!(patrol.guard_loop(), None);
+
+        let loops = patrol.loop_obstacles();
+        assert_eq!(
+            loops.iter().map(|&(p, _)| p).collect::<Vec<_>>(),
+            patrol.loop_positions()
+        );
+        // obstacle next to the guard starting position
+        let (_, info) = loops.iter().find(|&&(p, _)| p == (3, 6)).unwrap();
+        assert_eq!(info.start, 0);
+        assert_eq!(info.length, 4);
+        assert_eq!(
+            info.states,
+            [(4, 1, 1), (8, 1, 2), (8, 6, 3), (4, 6, 0)]
+                .into_iter()
+                .collect()
+        );
+
+        // turning back and forth between two obstacles
+        let patrol = Patrol::new(".#.\n...\n.^.\n.#.").with_turn(Turn::Reverse);
+        let info = patrol.guard_loop().unwrap();
+        assert_eq!((info.start, info.length), (0, 2));
+        let loops = patrol.loop_obstacles();
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].0, (1, 1));
+        assert_eq!(loops[0].1.length, 2);
+    }
 }
@@ -1,175 +1,684 @@
-use gxhash::HashMap;
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display, Formatter},
+    ops::AddAssign,
+    str::FromStr,
+};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
-type Input = Vec<(usize, Vec<usize>)>;
+type Input = Vec<Equation>;
 
-#[aoc_generator(day7)]
-pub fn parse(input: &str) -> Input {
-    let mut r = Vec::new();
-    for l in input.lines() {
-        let mut s = l.split(" ");
-        let res = s.next().unwrap();
-        let res = res[..res.len() - 1].parse::<usize>().unwrap();
+/// Arbitrary-precision unsigned integer, used when an equation doesn't fit in a `usize`.
+/// Little-endian base 2^32 limbs, without trailing zeros.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    fn normalize(mut self) -> Self {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        self
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn to_usize(&self) -> Option<usize> {
+        let mut res: usize = 0;
+        for &limb in self.limbs.iter().rev() {
+            res = res.checked_mul(1 << 32)?.checked_add(limb as usize)?;
+        }
+        Some(res)
+    }
+
+    fn bits(&self) -> usize {
+        self.limbs
+            .last()
+            .map_or(0, |&l| self.limbs.len() * 32 - l.leading_zeros() as usize)
+    }
+
+    fn bit(&self, i: usize) -> bool {
+        self.limbs
+            .get(i / 32)
+            .is_some_and(|&l| (l >> (i % 32)) & 1 == 1)
+    }
+
+    fn mul_add_small(&self, mul: u32, add: u32) -> Self {
+        let mut limbs = Vec::with_capacity(self.limbs.len() + 1);
+        let mut carry = add as u64;
+        for &l in &self.limbs {
+            let v = l as u64 * mul as u64 + carry;
+            limbs.push(v as u32);
+            carry = v >> 32;
+        }
+        limbs.push(carry as u32);
+        BigUint { limbs }.normalize()
+    }
+
+    fn div_rem_small(&self, div: u32) -> (Self, u32) {
+        let mut limbs = vec![0; self.limbs.len()];
+        let mut rem = 0u64;
+        for (i, &l) in self.limbs.iter().enumerate().rev() {
+            let v = (rem << 32) | l as u64;
+            limbs[i] = (v / div as u64) as u32;
+            rem = v % div as u64;
+        }
+        (BigUint { limbs }.normalize(), rem as u32)
+    }
+
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        if *self < *other {
+            return None;
+        }
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        let mut borrow = 0i64;
+        for (i, &l) in self.limbs.iter().enumerate() {
+            let mut v = l as i64 - other.limbs.get(i).copied().unwrap_or(0) as i64 - borrow;
+            borrow = (v < 0) as i64;
+            if v < 0 {
+                v += 1 << 32;
+            }
+            limbs.push(v as u32);
+        }
+        Some(BigUint { limbs }.normalize())
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let v = limbs[i + j] as u64 + a as u64 * b as u64 + carry;
+                limbs[i + j] = v as u32;
+                carry = v >> 32;
+            }
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+        BigUint { limbs }.normalize()
+    }
+
+    /// Quotient and remainder, `None` when dividing by zero.
+    pub fn div_rem(&self, other: &Self) -> Option<(Self, Self)> {
+        match other.limbs[..] {
+            [] => None,
+            [div] => {
+                let (q, r) = self.div_rem_small(div);
+                Some((q, BigUint::from(r as usize)))
+            }
+            _ => {
+                // binary long division, slow but only used as a fallback
+                let mut q = vec![0u32; self.limbs.len()];
+                let mut r = BigUint::default();
+                for i in (0..self.bits()).rev() {
+                    r = r.mul_add_small(2, self.bit(i) as u32);
+                    if r >= *other {
+                        r = r.checked_sub(other).unwrap();
+                        q[i / 32] |= 1 << (i % 32);
+                    }
+                }
+                Some((BigUint { limbs: q }.normalize(), r))
+            }
+        }
+    }
+
+    pub fn pow(&self, mut exp: u32) -> Self {
+        let mut res = BigUint::from(1);
+        let mut base = self.clone();
+        while exp > 0 {
+            if exp & 1 == 1 {
+                res = res.mul(&base);
+            }
+            base = base.mul(&base);
+            exp >>= 1;
+        }
+        res
+    }
+}
+
+impl From<usize> for BigUint {
+    fn from(mut v: usize) -> Self {
+        let mut limbs = Vec::new();
+        while v > 0 {
+            limbs.push(v as u32);
+            v = v.checked_shr(32).unwrap_or(0);
+        }
+        BigUint { limbs }
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.limbs
+            .len()
+            .cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq<usize> for BigUint {
+    fn eq(&self, other: &usize) -> bool {
+        self.to_usize() == Some(*other)
+    }
+}
 
-        let mut numbers = Vec::new();
-        for s1 in s {
-            numbers.push(s1.parse::<usize>().unwrap());
+impl AddAssign<&BigUint> for BigUint {
+    fn add_assign(&mut self, other: &BigUint) {
+        let len = self.limbs.len().max(other.limbs.len());
+        self.limbs.resize(len, 0);
+        let mut carry = 0u64;
+        for i in 0..len {
+            let v = self.limbs[i] as u64 + other.limbs.get(i).copied().unwrap_or(0) as u64 + carry;
+            self.limbs[i] = v as u32;
+            carry = v >> 32;
+        }
+        if carry > 0 {
+            self.limbs.push(carry as u32);
         }
-        r.push((res, numbers))
     }
-    r
 }
 
-#[allow(dead_code)]
-fn dp(
-    ci: usize,
-    sum: usize,
-    target: usize,
-    numbers: &[usize],
-    part2: bool,
-    cache: &mut HashMap<(usize, usize), usize>,
-) -> usize {
-    if let Some(v) = cache.get(&(ci, sum)) {
-        return *v;
+impl FromStr for BigUint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("invalid number: {s:?}"));
+        }
+        Ok(s.bytes().fold(BigUint::default(), |acc, b| {
+            acc.mul_add_small(10, (b - b'0') as u32)
+        }))
     }
-    if ci >= numbers.len() {
-        let v = (sum == target) as usize;
-        cache.insert((ci, sum), v);
-        return v;
+}
+
+impl Display for BigUint {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // base 10^9 chunks, from the least significant one
+        let mut chunks = Vec::new();
+        let mut cur = self.clone();
+        while !cur.is_zero() {
+            let (q, r) = cur.div_rem_small(1_000_000_000);
+            chunks.push(r);
+            cur = q;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{chunk:09}")?;
+        }
+        Ok(())
     }
-    if sum > target {
-        cache.insert((ci, sum), 0);
-        return 0;
+}
+
+/// Error raised when a `usize` computation overflows, to retry it with a [`BigUint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow;
+
+/// Arithmetic needed by the equation solver, implemented by `usize` for speed and by
+/// [`BigUint`] when that overflows.
+pub trait Number: Clone + Eq + Ord {
+    fn from_usize(v: usize) -> Self;
+    fn to_u32(&self) -> Option<u32>;
+    fn add(&self, other: &Self) -> Result<Self, Overflow>;
+    /// `None` when the result would be negative
+    fn sub(&self, other: &Self) -> Option<Self>;
+    fn mul(&self, other: &Self) -> Result<Self, Overflow>;
+    /// `None` when dividing by zero
+    fn div_rem(&self, other: &Self) -> Option<(Self, Self)>;
+    /// `None` when the result is too large to be computed
+    fn pow(&self, exp: u32) -> Result<Option<Self>, Overflow>;
+    /// number of significant bits
+    fn bits(&self) -> usize;
+    /// 10 to the power of the number of decimal digits, to concatenate numbers
+    fn digits_pow(&self) -> Result<Self, Overflow>;
+}
+
+impl Number for usize {
+    fn from_usize(v: usize) -> Self {
+        v
     }
 
-    let num = numbers[ci];
-    if ci == 0 {
-        let res = dp(ci + 1, num, target, numbers, part2, cache);
-        cache.insert((ci, sum), res);
-        return res;
+    fn to_u32(&self) -> Option<u32> {
+        (*self).try_into().ok()
     }
 
-    let mut res = 0;
+    fn add(&self, other: &Self) -> Result<Self, Overflow> {
+        self.checked_add(*other).ok_or(Overflow)
+    }
 
-    // check '+'
-    res += dp(ci + 1, sum + num, target, numbers, part2, cache);
+    fn sub(&self, other: &Self) -> Option<Self> {
+        self.checked_sub(*other)
+    }
 
-    // check '*'
-    res += dp(ci + 1, sum * num, target, numbers, part2, cache);
+    fn mul(&self, other: &Self) -> Result<Self, Overflow> {
+        self.checked_mul(*other).ok_or(Overflow)
+    }
 
-    // check '||'
-    if part2 {
-        let conc = format!("{}{}", sum, num);
-        let conc = conc.parse::<usize>().unwrap();
-        res += dp(ci + 1, conc, target, numbers, part2, cache);
+    fn div_rem(&self, other: &Self) -> Option<(Self, Self)> {
+        Some((self.checked_div(*other)?, self % other))
     }
 
-    cache.insert((ci, sum), res);
-    res
+    fn pow(&self, exp: u32) -> Result<Option<Self>, Overflow> {
+        self.checked_pow(exp).map(Some).ok_or(Overflow)
+    }
+
+    fn bits(&self) -> usize {
+        (usize::BITS - self.leading_zeros()) as usize
+    }
+
+    fn digits_pow(&self) -> Result<Self, Overflow> {
+        10_usize
+            .checked_pow(self.checked_ilog10().unwrap_or(0) + 1)
+            .ok_or(Overflow)
+    }
 }
 
-#[allow(dead_code)]
-fn brute1(target: usize, numbers: &[usize], part2: bool) -> bool {
-    let mut stack = vec![Vec::with_capacity(100); numbers.len()];
-    stack[0].push(numbers[0]);
-    for i in 1..numbers.len() {
-        let num = numbers[i];
-        if stack[i - 1].is_empty() {
-            return false;
-        }
-        while let Some(val) = stack[i - 1].pop() {
-            // check '+'
-            if val + num <= target {
-                stack[i].push(val + num);
+/// Powers of at least this many bits aren't computed.
+const MAX_POW_BITS: usize = 1 << 16;
+
+impl Number for BigUint {
+    fn from_usize(v: usize) -> Self {
+        BigUint::from(v)
+    }
+
+    fn to_u32(&self) -> Option<u32> {
+        self.to_usize()?.try_into().ok()
+    }
+
+    fn add(&self, other: &Self) -> Result<Self, Overflow> {
+        let mut res = self.clone();
+        res += other;
+        Ok(res)
+    }
+
+    fn sub(&self, other: &Self) -> Option<Self> {
+        self.checked_sub(other)
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self, Overflow> {
+        Ok(BigUint::mul(self, other))
+    }
+
+    fn div_rem(&self, other: &Self) -> Option<(Self, Self)> {
+        BigUint::div_rem(self, other)
+    }
+
+    fn pow(&self, exp: u32) -> Result<Option<Self>, Overflow> {
+        // self >= 2^(bits - 1), the result would have at least MAX_POW_BITS bits
+        if self.bits().saturating_sub(1).saturating_mul(exp as usize) >= MAX_POW_BITS {
+            return Ok(None);
+        }
+        Ok(Some(BigUint::pow(self, exp)))
+    }
+
+    fn bits(&self) -> usize {
+        BigUint::bits(self)
+    }
+
+    fn digits_pow(&self) -> Result<Self, Overflow> {
+        let digits = self.to_string().len() as u32;
+        Ok(BigUint::from(10).pow(digits))
+    }
+}
+
+/// Binary operator, applied left to right on the equation numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Operator {
+    Add,
+    Mul,
+    Concat,
+    Sub,
+    Div,
+    Pow,
+}
+
+pub const PART1_OPERATORS: [Operator; 2] = [Operator::Add, Operator::Mul];
+pub const PART2_OPERATORS: [Operator; 3] = [Operator::Add, Operator::Mul, Operator::Concat];
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Operator::Add => write!(f, "+"),
+            Operator::Mul => write!(f, "*"),
+            Operator::Concat => write!(f, "||"),
+            Operator::Sub => write!(f, "-"),
+            Operator::Div => write!(f, "/"),
+            Operator::Pow => write!(f, "^"),
+        }
+    }
+}
+
+fn div_exact<N: Number>(val: &N, num: &N) -> Option<N> {
+    match val.div_rem(num) {
+        Some((q, r)) if r == N::from_usize(0) => Some(q),
+        _ => None,
+    }
+}
+
+impl Operator {
+    /// `val op num`, `None` if it is not defined.
+    pub fn apply<N: Number>(self, val: &N, num: &N) -> Result<Option<N>, Overflow> {
+        Ok(match self {
+            Operator::Add => Some(val.add(num)?),
+            Operator::Mul => Some(val.mul(num)?),
+            Operator::Concat => Some(num.digits_pow()?.mul(val)?.add(num)?),
+            Operator::Sub => val.sub(num),
+            Operator::Div => div_exact(val, num),
+            Operator::Pow => match num.to_u32() {
+                Some(exp) => val.pow(exp)?,
+                None => None,
+            },
+        })
+    }
+
+    /// The value `val` such that `val op num == res`, used to search from the target backwards.
+    pub fn invert<N: Number>(self, res: &N, num: &N) -> Result<Option<N>, Overflow> {
+        Ok(match self {
+            Operator::Add => res.sub(num),
+            Operator::Mul => div_exact(res, num),
+            Operator::Concat => {
+                let pow = num.digits_pow()?;
+                match res.div_rem(&pow) {
+                    Some((q, r)) if r == *num => Some(q),
+                    _ => None,
+                }
+            }
+            Operator::Sub => Some(res.add(num)?),
+            Operator::Div => {
+                if *num == N::from_usize(0) {
+                    return Ok(None);
+                }
+                Some(res.mul(num)?)
             }
+            Operator::Pow => {
+                let Some(exp) = num.to_u32().filter(|&e| e > 0) else {
+                    return Ok(None);
+                };
+                // integer root by binary search, overflowing powers are too large
+                let (one, two) = (N::from_usize(1), N::from_usize(2));
+                let (mut lo, mut hi) = (N::from_usize(0), res.clone());
+                while lo < hi {
+                    let mid = lo.add(&hi)?.add(&one)?.div_rem(&two).unwrap().0;
+                    // mid >= 2^(bits - 1), skip the powers known to be larger than res
+                    let min_bits = mid.bits().saturating_sub(1).saturating_mul(exp as usize);
+                    let fits =
+                        min_bits < res.bits() && matches!(mid.pow(exp), Ok(Some(v)) if v <= *res);
+                    if fits {
+                        lo = mid;
+                    } else {
+                        hi = mid.sub(&one).unwrap();
+                    }
+                }
+                (lo.pow(exp)?.as_ref() == Some(res)).then_some(lo)
+            }
+        })
+    }
+}
+
+/// Calibration equation, kept in machine integers when every value fits in a `usize`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Equation {
+    target: BigUint,
+    numbers: Vec<BigUint>,
+    small: Option<(usize, Vec<usize>)>,
+}
 
-            // check '*'
-            if val * num <= target {
-                stack[i].push(val * num);
+impl Equation {
+    pub fn new(target: BigUint, numbers: Vec<BigUint>) -> Result<Self, String> {
+        if numbers.is_empty() {
+            return Err(format!("equation without numbers: {target}"));
+        }
+        let small = target
+            .to_usize()
+            .zip(numbers.iter().map(|n| n.to_usize()).collect());
+        Ok(Equation {
+            target,
+            numbers,
+            small,
+        })
+    }
+
+    pub fn target(&self) -> &BigUint {
+        &self.target
+    }
+
+    pub fn numbers(&self) -> &[BigUint] {
+        &self.numbers
+    }
+
+    /// Evaluates the numbers with the operators between them, left to right, `None` if there
+    /// isn't one operator less than numbers, an operation isn't defined or a power is too large
+    /// to be computed.
+    pub fn evaluate(&self, operators: &[Operator]) -> Option<BigUint> {
+        if operators.len() + 1 != self.numbers.len() {
+            return None;
+        }
+        self.numbers[1..]
+            .iter()
+            .zip(operators)
+            .try_fold(self.numbers[0].clone(), |val, (num, op)| {
+                op.apply(&val, num).unwrap()
+            })
+    }
+
+    pub fn is_solvable(&self, operators: &[Operator]) -> bool {
+        if let Some((target, numbers)) = &self.small {
+            if let Ok(res) = brute2(target, numbers, operators) {
+                return res;
             }
+        }
+        brute2(&self.target, &self.numbers, operators).unwrap()
+    }
 
-            // check '||'
-            if part2 {
-                let conc: usize = 10_usize.pow(num.ilog(10) + 1) * val + num;
-                if conc <= target {
-                    stack[i].push(conc);
-                }
+    fn solutions(&self, operators: &[Operator], all: bool) -> Vec<Vec<Operator>> {
+        let mut res = Vec::new();
+        if let Some((target, numbers)) = &self.small {
+            if solve_rev(target, numbers, operators, &mut Vec::new(), &mut res, all).is_ok() {
+                return res;
             }
+            res.clear();
         }
+        solve_rev(
+            &self.target,
+            &self.numbers,
+            operators,
+            &mut Vec::new(),
+            &mut res,
+            all,
+        )
+        .unwrap();
+        res
+    }
+
+    /// One sequence of operators making the equation reach its target, found by undoing the
+    /// operators from the target backwards.
+    pub fn solve(&self, operators: &[Operator]) -> Option<Vec<Operator>> {
+        self.solutions(operators, false).pop()
+    }
+
+    /// All the sequences of operators making the equation reach its target.
+    pub fn solve_all(&self, operators: &[Operator]) -> Vec<Vec<Operator>> {
+        self.solutions(operators, true)
     }
-    stack[numbers.len() - 1].contains(&target)
 }
 
-fn brute2(target: usize, numbers: &[usize], part2: bool) -> bool {
-    let mut stack = vec![Vec::with_capacity(100); numbers.len() + 1];
-    stack[numbers.len()].push(target);
-    for i in (0..numbers.len()).rev() {
-        let num = numbers[i];
-        if stack[i + 1].is_empty() {
-            return false;
+impl FromStr for Equation {
+    type Err = String;
+
+    fn from_str(l: &str) -> Result<Self, Self::Err> {
+        let (target, numbers) = l
+            .split_once(": ")
+            .ok_or(format!("invalid equation: {l:?}"))?;
+        let numbers = numbers
+            .split(" ")
+            .map(|n| n.parse())
+            .collect::<Result<Vec<_>, _>>()?;
+        Equation::new(target.parse()?, numbers)
+    }
+}
+
+#[aoc_generator(day7)]
+pub fn parse(input: &str) -> Input {
+    input.lines().map(|l| l.parse().unwrap()).collect()
+}
+
+/// Whether `val op num == res` for any `val`, which can't be found by inverting `op`.
+fn absorbs<N: Number>(op: Operator, res: &N, num: &N) -> bool {
+    *num == N::from_usize(0)
+        && match op {
+            Operator::Mul => *res == N::from_usize(0),
+            Operator::Pow => *res == N::from_usize(1),
+            _ => false,
         }
-        while let Some(val) = stack[i + 1].pop() {
-            // check '+'
-            if ((val as isize) - (num as isize)) >= 0 {
-                stack[i].push(val - num);
-            }
+}
 
-            // check '*'
-            if val % num == 0 {
-                stack[i].push(val / num);
+/// Sequences of operators for which `val` followed by `numbers` evaluate to a defined value.
+fn forward<N: Number>(
+    val: &N,
+    numbers: &[N],
+    operators: &[Operator],
+    cur: &mut Vec<Operator>,
+    res: &mut Vec<Vec<Operator>>,
+    all: bool,
+) -> Result<(), Overflow> {
+    let Some((num, rest)) = numbers.split_first() else {
+        res.push(cur.clone());
+        return Ok(());
+    };
+    for &op in operators {
+        if let Some(next) = op.apply(val, num)? {
+            cur.push(op);
+            forward(&next, rest, operators, cur, res, all)?;
+            cur.pop();
+            if !all && !res.is_empty() {
+                break;
             }
+        }
+    }
+    Ok(())
+}
 
-            // check '||'
-            if part2 {
-                let v = val % 10_usize.pow(num.ilog(10) + 1);
-                if v == num {
-                    stack[i].push(val / 10_usize.pow(num.ilog(10) + 1));
+fn brute2<N: Number>(target: &N, numbers: &[N], operators: &[Operator]) -> Result<bool, Overflow> {
+    let mut stack = vec![Vec::with_capacity(100); numbers.len()];
+    stack[numbers.len() - 1].push(target.clone());
+    for i in (1..numbers.len()).rev() {
+        let num = &numbers[i];
+        if stack[i].is_empty() {
+            return Ok(false);
+        }
+        // whether the numbers before this one evaluate to anything
+        let mut defined = None;
+        while let Some(val) = stack[i].pop() {
+            for op in operators {
+                if absorbs(*op, &val, num) {
+                    if defined.is_none() {
+                        let mut res = Vec::new();
+                        forward(
+                            &numbers[0],
+                            &numbers[1..i],
+                            operators,
+                            &mut Vec::new(),
+                            &mut res,
+                            false,
+                        )?;
+                        defined = Some(!res.is_empty());
+                    }
+                    if defined == Some(true) {
+                        return Ok(true);
+                    }
+                }
+                if let Some(prev) = op.invert(&val, num)? {
+                    stack[i - 1].push(prev);
                 }
             }
         }
     }
-    stack[0].contains(&0)
+    Ok(stack[0].contains(&numbers[0]))
 }
 
-#[aoc(day7, part1)]
-pub fn part1(input: &Input) -> usize {
-    let mut sum = 0;
-    for (target, numbers) in input {
-        if brute2(*target, numbers, false) {
-            sum += target;
+fn solve_rev<N: Number>(
+    val: &N,
+    numbers: &[N],
+    operators: &[Operator],
+    cur: &mut Vec<Operator>,
+    res: &mut Vec<Vec<Operator>>,
+    all: bool,
+) -> Result<(), Overflow> {
+    let (num, rest) = numbers.split_last().unwrap();
+    if rest.is_empty() {
+        if val == num {
+            res.push(cur.iter().rev().copied().collect());
         }
-        /*
-        let mut cache = HashMap::new();
-        let res = dp(0, numbers[0], *target, numbers, false, &mut cache);
-        if res > 0 {
-            sum += target;
+        return Ok(());
+    }
+    for &op in operators {
+        if absorbs(op, val, num) {
+            // any value works before the operator, the numbers before it only need to be defined
+            let mut prefixes = Vec::new();
+            forward(
+                &rest[0],
+                &rest[1..],
+                operators,
+                &mut Vec::new(),
+                &mut prefixes,
+                all,
+            )?;
+            for mut prefix in prefixes {
+                prefix.push(op);
+                prefix.extend(cur.iter().rev());
+                res.push(prefix);
+            }
+        }
+        if let Some(prev) = op.invert(val, num)? {
+            cur.push(op);
+            solve_rev(&prev, rest, operators, cur, res, all)?;
+            cur.pop();
+        }
+        if !all && !res.is_empty() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[aoc(day7, part1)]
+pub fn part1(input: &Input) -> BigUint {
+    let mut sum = BigUint::default();
+    for equation in input {
+        if equation.is_solvable(&PART1_OPERATORS) {
+            sum += equation.target();
         }
-        */
     }
     sum
 }
 
 #[aoc(day7, part2)]
-pub fn part2(input: &Input) -> usize {
-    let mut sum: usize = 0;
-    for (target, numbers) in input {
-        if brute2(*target, numbers, true) {
-            sum += target;
-        }
-        /*
-        let mut cache = HashMap::new();
-        let res = dp(0, numbers[0], *target, numbers, true, &mut cache);
-        if res > 0 {
-            sum = sum.checked_add(*target).unwrap();
+pub fn part2(input: &Input) -> BigUint {
+    let mut sum = BigUint::default();
+    for equation in input {
+        if equation.is_solvable(&PART2_OPERATORS) {
+            sum += equation.target();
         }
-        */
     }
     sum
 }
 
 #[cfg(test)]
 mod test {
+    use itertools::Itertools;
+
     use super::*;
 
     const EXAMPLE: &str = r#"190: 10 19
@@ -191,4 +700,116 @@ mod test {
     fn part2_example() {
         assert_eq!(part2(&parse(EXAMPLE)), 11387);
     }
+
+    fn equation(s: &str) -> Equation {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn solve_example() {
+        use Operator::*;
+
+        assert_eq!(
+            equation("190: 10 19").solve(&PART1_OPERATORS),
+            Some(vec![Mul])
+        );
+        assert_eq!(
+            equation("3267: 81 40 27").solve_all(&PART1_OPERATORS),
+            [vec![Mul, Add], vec![Add, Mul]]
+        );
+        assert_eq!(equation("83: 17 5").solve(&PART2_OPERATORS), None);
+        assert_eq!(
+            equation("7290: 6 8 6 15").solve(&PART2_OPERATORS),
+            Some(vec![Mul, Concat, Mul])
+        );
+
+        let ops = [Add, Mul, Sub, Div, Pow];
+        let eq = equation("7: 4 2 3 6");
+        let solutions = eq.solve_all(&ops);
+        assert!(solutions.contains(&vec![Pow, Sub, Sub]));
+        for sol in solutions {
+            assert_eq!(eq.evaluate(&sol), Some(BigUint::from(7)));
+        }
+        // one operator per pair of consecutive numbers
+        assert_eq!(eq.evaluate(&[Add, Add]), None);
+        assert_eq!(eq.evaluate(&[Add, Add, Add, Add]), None);
+    }
+
+    #[test]
+    fn zero_operands() {
+        use Operator::*;
+
+        assert_eq!(equation("0: 5 0").solve(&[Mul]), Some(vec![Mul]));
+        assert_eq!(equation("1: 5 0").solve(&[Pow]), Some(vec![Pow]));
+        assert!(equation("0: 5 0").is_solvable(&PART1_OPERATORS));
+        assert_eq!(
+            equation("0: 3 4 0").solve_all(&PART1_OPERATORS),
+            [vec![Add, Mul], vec![Mul, Mul]]
+        );
+        assert!(Equation::new(BigUint::from(1), vec![]).is_err());
+
+        // every equation of 2 or 3 small numbers against all the operator sequences
+        let ops = [Add, Mul, Concat, Sub, Div, Pow];
+        for len in 2..=3 {
+            for values in (0..len).map(|_| 0..4).multi_cartesian_product() {
+                for target in 0..10 {
+                    let numbers = values.iter().map(|&n| BigUint::from(n)).collect();
+                    let eq = Equation::new(BigUint::from(target), numbers).unwrap();
+                    let mut expected = (1..len)
+                        .map(|_| ops)
+                        .multi_cartesian_product()
+                        .filter(|seq| eq.evaluate(seq).is_some_and(|v| v == target))
+                        .collect::<Vec<_>>();
+                    let mut solutions = eq.solve_all(&ops);
+                    solutions.sort();
+                    expected.sort();
+                    assert_eq!(solutions, expected, "{target}: {values:?}");
+                    assert_eq!(eq.is_solvable(&ops), !expected.is_empty());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn big_equations() {
+        use Operator::*;
+
+        let big = "340282366920938463463374607431768211456";
+        assert_eq!(big.parse::<BigUint>().unwrap().to_string(), big);
+        assert_eq!(BigUint::from(1 << 32).pow(4).to_string(), big);
+
+        // 2^128 = (2^64)^2, the operands and the target don't fit in a usize
+        let eq = equation(&format!("{big}: 18446744073709551616 18446744073709551616"));
+        assert_eq!(eq.solve(&PART1_OPERATORS), Some(vec![Mul]));
+        assert_eq!(
+            equation(&format!("{big}: 4 64")).solve(&[Pow]),
+            Some(vec![Pow])
+        );
+
+        // the operands fit but the intermediate values of the backward search don't
+        let eq = equation("1: 18446744073709551615 2 2 18446744073709551615");
+        assert!(eq.small.is_some());
+        assert_eq!(eq.solve(&[Sub, Div, Mul]), Some(vec![Mul, Div, Div]));
+
+        // concatenating a 20-digit number overflows in the usize search
+        let eq = equation("110000000000000000000: 1 10000000000000000000");
+        assert_eq!(eq.solve(&PART2_OPERATORS), Some(vec![Concat]));
+        assert_eq!(
+            part2(&vec![eq.clone(), eq]).to_string(),
+            "220000000000000000000"
+        );
+
+        // the roots of huge powers are found without computing them
+        let eq = equation(&format!("{big}: 2 4000000000"));
+        assert_eq!(eq.solve(&[Pow]), None);
+        assert_eq!(eq.evaluate(&[Pow]), None);
+        assert_eq!(
+            equation("1: 1 4000000000").evaluate(&[Pow]),
+            Some(BigUint::from(1))
+        );
+        assert_eq!(
+            equation(&format!("{big}: {big} 1")).solve(&[Pow]),
+            Some(vec![Pow])
+        );
+    }
 }
@@ -5,6 +5,8 @@ use aoc_runner_derive::{aoc, aoc_generator};
 use aoc_utils::{grid::Grid, point::Point};
 use itertools::Itertools;
 
+use crate::math::gcd;
+
 type Input = (Grid<u8>, HashMap<u8, Vec<Point>>);
 
 #[aoc_generator(day8)]
@@ -20,55 +22,100 @@ pub fn parse(input: &str) -> Input {
     (grid, pos)
 }
 
-#[aoc(day8, part1)]
-pub fn part1(input: &Input) -> u32 {
-    let mut seen = HashSet::new();
+/// Where the antinodes of a pair of antennas are, relative to the step between them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Harmonics {
+    /// `k` steps beyond each antenna for each given `k`, 0 being the antennas themselves
+    Multiples(Vec<i64>),
+    /// every whole step beyond each antenna, including the antennas
+    AllMultiples,
+    /// every grid position in line with the antennas, stepping by the step divided by the gcd
+    /// of its coordinates
+    Lattice,
+}
+
+/// Antinodes inside the grid, grouped by the frequency of the antennas creating them.
+pub fn antinodes(input: &Input, harmonics: &Harmonics) -> HashMap<u8, HashSet<Point>> {
     let (grid, poss) = input;
-    for pos in poss.values() {
+    let mut res = HashMap::new();
+    for (&freq, pos) in poss {
+        let seen: &mut HashSet<Point> = res.entry(freq).or_insert_with(HashSet::new);
         for p in pos.iter().combinations(2) {
             let p1 = *p[0];
             let p2 = *p[1];
             let diff = p2 - p1;
-            if grid.contains(p1 - diff) {
-                seen.insert(p1 - diff);
-            }
-            if grid.contains(p2 + diff) {
-                seen.insert(p2 + diff);
+            match harmonics {
+                Harmonics::Multiples(ks) => {
+                    for &k in ks {
+                        for a in [p1 - diff * k, p2 + diff * k] {
+                            if grid.contains(a) {
+                                seen.insert(a);
+                            }
+                        }
+                    }
+                }
+                Harmonics::AllMultiples => {
+                    for (mut a, step) in [(p1, -diff), (p2, diff)] {
+                        while grid.contains(a) {
+                            seen.insert(a);
+                            a += step;
+                        }
+                    }
+                }
+                Harmonics::Lattice => {
+                    let g = gcd(diff.x, diff.y);
+                    let step = Point::new(diff.x / g, diff.y / g);
+                    for (mut a, step) in [(p1, -step), (p1 + step, step)] {
+                        while grid.contains(a) {
+                            seen.insert(a);
+                            a += step;
+                        }
+                    }
+                }
             }
         }
     }
-    seen.len() as u32
+    res
 }
 
-#[aoc(day8, part2)]
-pub fn part2(input: &Input) -> u32 {
-    let mut seen = HashSet::new();
-    let (grid, poss) = input;
-    for pos in poss.values() {
-        for p in pos.iter().combinations(2) {
-            let mut p1 = *p[0];
-            let mut p2 = *p[1];
-            let diff = p2 - p1;
-            seen.insert(p1);
-            seen.insert(p2);
-
-            let mut found = true;
-            while found {
-                found = false;
-                if grid.contains(p1 - diff) {
-                    found = true;
-                    seen.insert(p1 - diff);
-                    p1 -= diff;
-                }
-                if grid.contains(p2 + diff) {
-                    found = true;
-                    seen.insert(p2 + diff);
-                    p2 += diff;
-                }
+fn count(antinodes: &HashMap<u8, HashSet<Point>>) -> u32 {
+    antinodes.values().flatten().collect::<HashSet<_>>().len() as u32
+}
+
+/// Draws the grid with a `#` on each antinode, only keeping the ones of `frequency` if given.
+/// Antennas are drawn over the antinodes, as in the puzzle illustrations.
+pub fn render(
+    input: &Input,
+    antinodes: &HashMap<u8, HashSet<Point>>,
+    frequency: Option<u8>,
+) -> String {
+    let (grid, _) = input;
+    let mut bytes = grid.bytes.clone();
+    for (&freq, points) in antinodes {
+        if frequency.is_some_and(|f| f != freq) {
+            continue;
+        }
+        for &p in points {
+            let i = p.y as usize * grid.width + p.x as usize;
+            if bytes[i] == b'.' {
+                bytes[i] = b'#';
             }
         }
     }
-    seen.len() as u32
+    bytes
+        .chunks(grid.width)
+        .map(|l| String::from_utf8_lossy(l))
+        .join("\n")
+}
+
+#[aoc(day8, part1)]
+pub fn part1(input: &Input) -> u32 {
+    count(&antinodes(input, &Harmonics::Multiples(vec![1])))
+}
+
+#[aoc(day8, part2)]
+pub fn part2(input: &Input) -> u32 {
+    count(&antinodes(input, &Harmonics::Lattice))
 }
 
 #[cfg(test)]
@@ -97,4 +144,73 @@ mod test {
     fn part2_example() {
         assert_eq!(part2(&parse(EXAMPLE)), 34);
     }
+
+    #[test]
+    fn render_example() {
+        let input = parse(EXAMPLE);
+        let part1 = antinodes(&input, &Harmonics::Multiples(vec![1]));
+        assert_eq!(
+            render(&input, &part1, None),
+            r#"......#....#
+...#....0...
+....#0....#.
+..#....0....
+....0....#..
+.#....A.....
+...#........
+#......#....
+........A...
+.........A..
+..........#.
+..........#."#
+        );
+        let part2 = antinodes(&input, &Harmonics::Lattice);
+        assert_eq!(
+            render(&input, &part2, None),
+            r#"##....#....#
+.#.#....0...
+..#.#0....#.
+..##...0....
+....0....#..
+.#...#A....#
+...#..#.....
+#....#.#....
+..#.....A...
+....#....A..
+.#........#.
+...#......##"#
+        );
+        let only_a = render(&input, &part1, Some(b'A'));
+        assert_eq!(only_a.matches('#').count(), 5);
+        assert_eq!(render(&input, &part1, Some(b'x')), EXAMPLE);
+    }
+
+    #[test]
+    fn harmonics() {
+        let input = parse(
+            r#"..........
+..........
+..........
+....a.....
+..........
+......a...
+..........
+..........
+..........
+.........."#,
+        );
+        let freq = |harmonics| antinodes(&input, &harmonics)[&b'a'].len();
+        assert_eq!(freq(Harmonics::Multiples(vec![1])), 2);
+        assert_eq!(freq(Harmonics::Multiples(vec![0, 3])), 2);
+        assert_eq!(freq(Harmonics::AllMultiples), 4);
+        // the points halfway between the antennas are in line too
+        assert_eq!(freq(Harmonics::Lattice), 9);
+
+        let input = parse(EXAMPLE);
+        assert_eq!(count(&antinodes(&input, &Harmonics::AllMultiples)), 34);
+        let groups = antinodes(&input, &Harmonics::Multiples(vec![1]));
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[&b'A'].len(), 5);
+        assert!(groups[&b'A'].contains(&Point::new(10, 11)));
+    }
 }
@@ -1,5 +1,5 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::collections::BTreeSet;
+use std::{cmp::Reverse, collections::BinaryHeap};
 
 type Input = Vec<usize>;
 
@@ -13,6 +13,152 @@ pub fn parse(input: &str) -> Input {
     values
 }
 
+/// How to move the files to the start of the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// move the blocks one at a time from the end to the leftmost free block
+    Blocks,
+    /// move whole files, by decreasing id, to the leftmost free span large enough
+    FirstFit,
+    /// move whole files, by decreasing id, to the smallest free span large enough
+    BestFit,
+    /// pack all the files in order without fragmenting them, leaving the free space at the end
+    DefragmentToEnd,
+}
+
+/// Uncompressed disk layout, with the id of the file stored on each block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disk {
+    pub blocks: Vec<Option<usize>>,
+}
+
+impl Disk {
+    pub fn new(values: &Input) -> Self {
+        let mut blocks = Vec::new();
+        for (ci, &n) in values.iter().enumerate() {
+            let id = (ci % 2 == 0).then_some(ci / 2);
+            blocks.resize(blocks.len() + n, id);
+        }
+        Disk { blocks }
+    }
+
+    /// Renders the block map like in the puzzle statement, ids above 9 are written modulo 10.
+    pub fn render(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|b| match b {
+                Some(id) => char::from_digit((id % 10) as u32, 10).unwrap(),
+                None => '.',
+            })
+            .collect()
+    }
+
+    pub fn checksum(&self) -> usize {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| b.map(|id| i * id))
+            .sum()
+    }
+
+    /// First block and number of blocks of each file.
+    fn files(&self) -> Vec<(usize, usize)> {
+        let mut files = Vec::new();
+        for (i, &b) in self.blocks.iter().enumerate() {
+            if let Some(id) = b {
+                if id >= files.len() {
+                    files.resize(id + 1, (usize::MAX, 0));
+                }
+                files[id].0 = files[id].0.min(i);
+                files[id].1 += 1;
+            }
+        }
+        files
+    }
+
+    /// Start and length of each span of free blocks.
+    fn free_spans(&self) -> Vec<(usize, usize)> {
+        let mut spans: Vec<(usize, usize)> = Vec::new();
+        for (i, b) in self.blocks.iter().enumerate() {
+            if b.is_some() {
+                continue;
+            }
+            match spans.last_mut() {
+                Some((start, len)) if *start + *len == i => *len += 1,
+                _ => spans.push((i, 1)),
+            }
+        }
+        spans
+    }
+
+    fn move_file(&mut self, id: usize, from: usize, to: usize, len: usize) {
+        for b in self.blocks[from..]
+            .iter_mut()
+            .filter(|b| **b == Some(id))
+            .take(len)
+        {
+            *b = None;
+        }
+        self.blocks[to..to + len].fill(Some(id));
+    }
+
+    pub fn compact(&self, strategy: Strategy) -> Disk {
+        let mut disk = self.clone();
+        match strategy {
+            Strategy::Blocks => {
+                let blocks = &mut disk.blocks;
+                let (mut free, mut used) = (0, blocks.len());
+                loop {
+                    while free < blocks.len() && blocks[free].is_some() {
+                        free += 1;
+                    }
+                    while used > 0 && blocks[used - 1].is_none() {
+                        used -= 1;
+                    }
+                    if used == 0 || free >= used - 1 {
+                        break;
+                    }
+                    blocks.swap(free, used - 1);
+                }
+            }
+            Strategy::FirstFit | Strategy::BestFit => {
+                let mut spans = self.free_spans();
+                for (id, (start, len)) in self.files().into_iter().enumerate().rev() {
+                    if len == 0 {
+                        continue;
+                    }
+                    let candidates = spans
+                        .iter()
+                        .enumerate()
+                        .take_while(|(_, &(s, _))| s < start)
+                        .filter(|(_, &(_, l))| l >= len);
+                    let span = if strategy == Strategy::FirstFit {
+                        candidates.min_by_key(|(_, &(s, _))| s)
+                    } else {
+                        candidates.min_by_key(|(_, &(s, l))| (l, s))
+                    };
+                    if let Some((si, &(s, _))) = span {
+                        disk.move_file(id, start, s, len);
+                        spans[si].0 += len;
+                        spans[si].1 -= len;
+                    }
+                }
+            }
+            Strategy::DefragmentToEnd => {
+                let mut files = self.files().into_iter().enumerate().collect::<Vec<_>>();
+                files.sort_by_key(|&(_, (start, _))| start);
+                disk.blocks.fill(None);
+                let mut i = 0;
+                for (id, (_, len)) in files {
+                    disk.blocks[i..i + len].fill(Some(id));
+                    i += len;
+                }
+            }
+        }
+        disk
+    }
+}
+
 #[aoc(day9, part1)]
 pub fn part1(input: &Input) -> usize {
     let values = input.clone();
@@ -78,6 +224,46 @@ pub fn part1(input: &Input) -> usize {
 
 #[aoc(day9, part2)]
 pub fn part2(input: &Input) -> usize {
+    // start position of each span of free blocks, in one min-heap per span size
+    let mut free: [BinaryHeap<Reverse<usize>>; 10] = Default::default();
+    let mut files = Vec::with_capacity(input.len() / 2 + 1);
+    let mut pos = 0;
+    for (ci, &n) in input.iter().enumerate() {
+        if ci % 2 == 0 {
+            files.push((pos, n));
+        } else if n != 0 {
+            free[n].push(Reverse(pos));
+        }
+        pos += n;
+    }
+
+    let mut sum = 0;
+    for (id, &(start, n)) in files.iter().enumerate().rev() {
+        // leftmost span large enough, among the smallest position of each size
+        let best = (n.max(1)..free.len())
+            .filter_map(|size| free[size].peek().map(|&Reverse(p)| (p, size)))
+            .filter(|&(p, _)| p < start)
+            .min();
+        let start = match best {
+            Some((p, size)) => {
+                free[size].pop();
+                if size > n {
+                    free[size - n].push(Reverse(p + n));
+                }
+                p
+            }
+            None => start,
+        };
+        sum += id * (start * n + n * n.saturating_sub(1) / 2);
+    }
+    sum
+}
+
+// previous version, scanning the free spans in order for each file, kept to check the new one
+#[cfg(test)]
+fn part2_btree(input: &Input) -> usize {
+    use std::collections::BTreeSet;
+
     let values = input.clone();
     let mut id = 0;
     let mut buckets = vec![vec![]; values.len()];
@@ -150,6 +336,8 @@ pub fn part2(input: &Input) -> usize {
 
 #[cfg(test)]
 mod test {
+    use crate::test_utils::Rng;
+
     use super::*;
 
     const EXAMPLE: &str = r#"2333133121414131402"#;
@@ -163,4 +351,83 @@ mod test {
     fn part2_example() {
         assert_eq!(part2(&parse(EXAMPLE)), 2858);
     }
+
+    #[test]
+    fn disk_compaction() {
+        let disk = Disk::new(&parse(EXAMPLE));
+        assert_eq!(disk.render(), "00...111...2...333.44.5555.6666.777.888899");
+
+        let compacted = disk.compact(Strategy::Blocks);
+        assert_eq!(
+            compacted.render(),
+            "0099811188827773336446555566.............."
+        );
+        assert_eq!(compacted.checksum(), 1928);
+
+        let compacted = disk.compact(Strategy::FirstFit);
+        assert_eq!(
+            compacted.render(),
+            "00992111777.44.333....5555.6666.....8888.."
+        );
+        assert_eq!(compacted.checksum(), 2858);
+
+        let compacted = disk.compact(Strategy::BestFit);
+        assert_eq!(
+            compacted.render(),
+            "00992111777.44.333....5555.6666.....8888.."
+        );
+
+        let compacted = disk.compact(Strategy::DefragmentToEnd);
+        assert_eq!(
+            compacted.render(),
+            "0011123334455556666777888899.............."
+        );
+
+        // best fit keeps the large span for later
+        let disk = Disk::new(&parse("14122"));
+        assert_eq!(disk.render(), "0....1..22");
+        assert_eq!(disk.compact(Strategy::FirstFit).render(), "0221......");
+        assert_eq!(disk.compact(Strategy::BestFit).render(), "01....22..");
+    }
+
+    /// Random dense disk map with `nfiles` files.
+    fn generate(nfiles: usize, seed: u64) -> Input {
+        let mut rng = Rng::new(seed);
+        let mut rand = |n: u64| rng.below(n) as usize;
+        let mut values = Vec::with_capacity(nfiles * 2);
+        for _ in 0..nfiles {
+            values.push(rand(9) + 1);
+            values.push(rand(10));
+        }
+        values.pop();
+        values
+    }
+
+    #[test]
+    fn part2_generated() {
+        for seed in 0..3 {
+            let values = generate(5000, seed);
+            let expected = Disk::new(&values).compact(Strategy::FirstFit).checksum();
+            assert_eq!(part2(&values), expected);
+            assert_eq!(part2_btree(&values), expected);
+        }
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --release -- --ignored --nocapture"]
+    fn part2_bench() {
+        use std::time::Instant;
+
+        for nfiles in [100_000, 1_000_000] {
+            let values = generate(nfiles, 42);
+            let now = Instant::now();
+            let heaps = part2(&values);
+            let heaps_time = now.elapsed();
+            let now = Instant::now();
+            let btree = part2_btree(&values);
+            let btree_time = now.elapsed();
+            assert_eq!(heaps, btree);
+            println!("{nfiles} files: heaps {heaps_time:?}, btree {btree_time:?}");
+        }
+    }
 }
@@ -1,27 +1,30 @@
-mod day1;
-mod day10;
+pub mod day1;
+pub mod day10;
 mod day11;
 mod day12;
 mod day13;
-mod day14;
+pub mod day14;
 mod day15;
 mod day16;
 mod day17;
 mod day18;
-mod day19;
-mod day2;
+pub mod day19;
+pub mod day2;
 mod day20;
 mod day21;
 mod day22;
-mod day23;
+pub mod day23;
 mod day24;
 mod day25;
-mod day3;
-mod day4;
-mod day5;
-mod day6;
-mod day7;
-mod day8;
-mod day9;
+pub mod day3;
+pub mod day4;
+pub mod day5;
+pub mod day6;
+pub mod day7;
+pub mod day8;
+pub mod day9;
+mod math;
+#[cfg(test)]
+mod test_utils;
 
 aoc_runner_derive::aoc_lib! { year = 2024 }
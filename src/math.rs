@@ -0,0 +1,8 @@
+/// Greatest common divisor, always positive unless both numbers are 0.
+pub fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}